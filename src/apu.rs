@@ -0,0 +1,940 @@
+use crate::cart::Timing;
+use crate::mixer::{Mixer, DEFAULT_SAMPLE_RATE};
+
+// 2A03 audio processing unit. clocked once per cpu cycle from the bus.
+// channel timers run on apu cycles (every other cpu cycle) except where noted.
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+const TRIANGLE_SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+// ntsc timer periods in cpu cycles
+const NOISE_PERIOD: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+const DMC_RATE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+const PAL_NOISE_PERIOD: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+const PAL_DMC_RATE: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+// ntsc frame sequencer steps in cpu cycles after a reset
+const FRAME_STEP_1: u32 = 7457;
+const FRAME_STEP_2: u32 = 14913;
+const FRAME_STEP_3: u32 = 22371;
+const FRAME_STEP_4: u32 = 29829;
+const FRAME_STEP_5: u32 = 37281;
+
+// the timings that differ between the ntsc and the pal 2A03
+struct Tables {
+    noise_period: [u16; 16],
+    dmc_rate: [u16; 16],
+    // the 4-step sequence ends on the fourth step, the 5-step one on the fifth
+    frame_steps: [u32; 5],
+}
+
+const NTSC_TABLES: Tables = Tables {
+    noise_period: NOISE_PERIOD,
+    dmc_rate: DMC_RATE,
+    frame_steps: [FRAME_STEP_1, FRAME_STEP_2, FRAME_STEP_3, FRAME_STEP_4, FRAME_STEP_5],
+};
+
+const PAL_TABLES: Tables = Tables {
+    noise_period: PAL_NOISE_PERIOD,
+    dmc_rate: PAL_DMC_RATE,
+    frame_steps: [8313, 16627, 24939, 33253, 41565],
+};
+
+// cpu cycles a dmc sample fetch halts the cpu for
+const DMC_FETCH_STALL: u32 = 4;
+
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    // cpu cycles since the frame sequencer was reset
+    frame_cycle: u32,
+    five_step: bool,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    // a $4017 write resets the sequencer 3 or 4 cpu cycles later
+    frame_reset_delay: u8,
+    // channel timers are clocked on every other cpu cycle
+    even: bool,
+    frame_steps: [u32; 5],
+    pub mixer: Mixer,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Apu::new(Timing::NTSC)
+    }
+}
+
+impl Apu {
+    // pal consoles get the pal tables, everything else the ntsc ones
+    pub fn new(timing: Timing) -> Self {
+        let tables = match timing {
+            Timing::PAL => &PAL_TABLES,
+            _ => &NTSC_TABLES,
+        };
+        Apu {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::new(),
+            noise: Noise::new(&tables.noise_period),
+            dmc: Dmc::new(&tables.dmc_rate),
+            frame_cycle: 0,
+            five_step: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            frame_reset_delay: 0,
+            even: false,
+            frame_steps: tables.frame_steps,
+            mixer: Mixer::new(DEFAULT_SAMPLE_RATE, timing.cpu_clock()),
+        }
+    }
+
+    // $4000-$4017 except $4016. addr is the full cpu address.
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write(addr & 3, data),
+            0x4004..=0x4007 => self.pulse2.write(addr & 3, data),
+            0x4008..=0x400b => self.triangle.write(addr & 3, data),
+            0x400c..=0x400f => self.noise.write(addr & 3, data),
+            0x4010..=0x4013 => self.dmc.write(addr & 3, data),
+            0x4015 => {
+                self.pulse1.set_enabled(data & 0b0_0001 != 0);
+                self.pulse2.set_enabled(data & 0b0_0010 != 0);
+                self.triangle.set_enabled(data & 0b0_0100 != 0);
+                self.noise.set_enabled(data & 0b0_1000 != 0);
+                self.dmc.set_enabled(data & 0b1_0000 != 0);
+            }
+            // MI-- ----: 5-step mode, irq inhibit
+            0x4017 => {
+                self.five_step = data & 0b1000_0000 != 0;
+                self.irq_inhibit = data & 0b0100_0000 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+                self.frame_reset_delay = if self.even { 4 } else { 3 };
+            }
+            _ => {}
+        }
+    }
+
+    // $4015: IF-D NT21. length counter status of each channel, dmc bytes left,
+    // frame and dmc interrupt flags. reading clears the frame interrupt.
+    pub fn read_status(&mut self) -> u8 {
+        let r = (self.pulse1.length > 0) as u8
+            | ((self.pulse2.length > 0) as u8) << 1
+            | ((self.triangle.length > 0) as u8) << 2
+            | ((self.noise.length > 0) as u8) << 3
+            | ((self.dmc.bytes_remaining > 0) as u8) << 4
+            | (self.frame_irq as u8) << 6
+            | (self.dmc.irq as u8) << 7;
+        self.frame_irq = false;
+        r
+    }
+
+    // advance one cpu cycle
+    pub fn tick(&mut self) {
+        self.even = !self.even;
+        if self.even {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+            self.noise.clock_timer();
+        }
+        self.triangle.clock_timer();
+        self.dmc.clock_timer();
+        self.clock_frame_sequencer();
+        let pulse = self.pulse_output();
+        let tnd = self.tnd_output();
+        self.mixer.push(pulse, tnd);
+    }
+
+    fn clock_frame_sequencer(&mut self) {
+        if self.frame_reset_delay > 0 {
+            self.frame_reset_delay -= 1;
+            if self.frame_reset_delay == 0 {
+                self.frame_cycle = 0;
+                // entering 5-step mode clocks everything right away
+                if self.five_step {
+                    self.quarter_frame();
+                    self.half_frame();
+                }
+                return;
+            }
+        }
+
+        self.frame_cycle += 1;
+        let [step1, step2, step3, step4, step5] = self.frame_steps;
+        match (self.frame_cycle, self.five_step) {
+            (c, _) if c == step1 || c == step3 => self.quarter_frame(),
+            (c, _) if c == step2 => {
+                self.quarter_frame();
+                self.half_frame();
+            }
+            // the 4-step sequence raises the frame irq on its last three cycles
+            (c, false) if c == step4 - 1 => self.set_frame_irq(),
+            (c, false) if c == step4 => {
+                self.set_frame_irq();
+                self.quarter_frame();
+                self.half_frame();
+            }
+            (c, false) if c == step4 + 1 => {
+                self.set_frame_irq();
+                self.frame_cycle = 0;
+            }
+            (c, true) if c == step5 => {
+                self.quarter_frame();
+                self.half_frame();
+            }
+            (c, true) if c == step5 + 1 => self.frame_cycle = 0,
+            _ => {}
+        }
+    }
+
+    fn set_frame_irq(&mut self) {
+        if !self.irq_inhibit {
+            self.frame_irq = true;
+        }
+    }
+
+    // envelopes and the triangle's linear counter
+    fn quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.noise.envelope.clock();
+        self.triangle.clock_linear();
+    }
+
+    // length counters and sweep units
+    fn half_frame(&mut self) {
+        self.pulse1.clock_length();
+        self.pulse2.clock_length();
+        self.triangle.clock_length();
+        self.noise.clock_length();
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_sweep();
+    }
+
+    pub fn frame_irq(&self) -> bool {
+        self.frame_irq
+    }
+
+    pub fn dmc_irq(&self) -> bool {
+        self.dmc.irq
+    }
+
+    // address the dmc wants to read its next sample byte from
+    pub fn dmc_request(&self) -> Option<u16> {
+        if self.dmc.buffer.is_none() && self.dmc.bytes_remaining > 0 {
+            Some(self.dmc.current_addr)
+        } else {
+            None
+        }
+    }
+
+    // hand the byte read for `dmc_request` to the dmc. returns the cpu cycles it stole.
+    pub fn dmc_fill(&mut self, data: u8) -> u32 {
+        self.dmc.fill(data);
+        DMC_FETCH_STALL
+    }
+
+    // current 4 bit outputs of the pulse channels
+    pub fn pulse_output(&self) -> (u8, u8) {
+        (self.pulse1.output(), self.pulse2.output())
+    }
+
+    // current outputs of triangle (4 bit), noise (4 bit) and dmc (7 bit)
+    pub fn tnd_output(&self) -> (u8, u8, u8) {
+        (self.triangle.output(), self.noise.output(), self.dmc.level)
+    }
+}
+
+// volume envelope shared by the pulse and noise channels
+struct Envelope {
+    start: bool,
+    // also the length counter halt flag
+    looping: bool,
+    constant: bool,
+    // constant volume, or the divider period
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    fn new() -> Self {
+        Envelope {
+            start: false,
+            looping: false,
+            constant: false,
+            volume: 0,
+            divider: 0,
+            decay: 0,
+        }
+    }
+
+    // --LC VVVV
+    fn write(&mut self, data: u8) {
+        self.looping = data & 0b0010_0000 != 0;
+        self.constant = data & 0b0001_0000 != 0;
+        self.volume = data & 0x0f;
+    }
+
+    fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.constant {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+struct Pulse {
+    // pulse 1 negates with ones' complement (subtracts one more), pulse 2 with twos' complement
+    ones_complement: bool,
+    enabled: bool,
+    duty: u8,
+    sequence: u8,
+    timer_period: u16,
+    timer: u16,
+    length: u8,
+    envelope: Envelope,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_reload: bool,
+    sweep_divider: u8,
+}
+
+impl Pulse {
+    fn new(ones_complement: bool) -> Self {
+        Pulse {
+            ones_complement: ones_complement,
+            enabled: false,
+            duty: 0,
+            sequence: 0,
+            timer_period: 0,
+            timer: 0,
+            length: 0,
+            envelope: Envelope::new(),
+            sweep_enabled: false,
+            sweep_period: 0,
+            sweep_negate: false,
+            sweep_shift: 0,
+            sweep_reload: false,
+            sweep_divider: 0,
+        }
+    }
+
+    fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            // DDLC VVVV
+            0 => {
+                self.duty = data >> 6;
+                self.envelope.write(data);
+            }
+            // EPPP NSSS
+            1 => {
+                self.sweep_enabled = data & 0b1000_0000 != 0;
+                self.sweep_period = (data >> 4) & 0b111;
+                self.sweep_negate = data & 0b0000_1000 != 0;
+                self.sweep_shift = data & 0b111;
+                self.sweep_reload = true;
+            }
+            2 => self.timer_period = (self.timer_period & 0x700) | data as u16,
+            // LLLL LTTT
+            _ => {
+                self.timer_period = (self.timer_period & 0xff) | ((data as u16 & 0b111) << 8);
+                if self.enabled {
+                    self.length = LENGTH_TABLE[(data >> 3) as usize];
+                }
+                self.sequence = 0;
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.length = 0;
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence = (self.sequence + 1) & 7;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if self.length > 0 && !self.envelope.looping {
+            self.length -= 1;
+        }
+    }
+
+    // the period the sweep unit would switch to. computed all the time,
+    // since a target above $7ff mutes the channel even with the sweep disabled.
+    fn sweep_target(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+        if self.sweep_negate {
+            let change = change + self.ones_complement as u16;
+            self.timer_period.saturating_sub(change)
+        } else {
+            self.timer_period + change
+        }
+    }
+
+    fn muted(&self) -> bool {
+        self.timer_period < 8 || self.sweep_target() > 0x7ff
+    }
+
+    fn clock_sweep(&mut self) {
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+            self.timer_period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.length == 0 || self.muted() || DUTY_TABLE[self.duty as usize][self.sequence as usize] == 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+struct Triangle {
+    enabled: bool,
+    // also the length counter halt flag
+    control: bool,
+    linear_reload_value: u8,
+    linear: u8,
+    linear_reload: bool,
+    timer_period: u16,
+    timer: u16,
+    sequence: u8,
+    length: u8,
+}
+
+impl Triangle {
+    fn new() -> Self {
+        Triangle {
+            enabled: false,
+            control: false,
+            linear_reload_value: 0,
+            linear: 0,
+            linear_reload: false,
+            timer_period: 0,
+            timer: 0,
+            sequence: 0,
+            length: 0,
+        }
+    }
+
+    fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            // CRRR RRRR
+            0 => {
+                self.control = data & 0b1000_0000 != 0;
+                self.linear_reload_value = data & 0x7f;
+            }
+            2 => self.timer_period = (self.timer_period & 0x700) | data as u16,
+            // LLLL LTTT
+            3 => {
+                self.timer_period = (self.timer_period & 0xff) | ((data as u16 & 0b111) << 8);
+                if self.enabled {
+                    self.length = LENGTH_TABLE[(data >> 3) as usize];
+                }
+                self.linear_reload = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.length = 0;
+        }
+    }
+
+    // the triangle timer runs at the cpu rate
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.length > 0 && self.linear > 0 {
+                self.sequence = (self.sequence + 1) & 31;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear = self.linear_reload_value;
+        } else if self.linear > 0 {
+            self.linear -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if self.length > 0 && !self.control {
+            self.length -= 1;
+        }
+    }
+
+    // a halted sequencer keeps outputting its current step
+    fn output(&self) -> u8 {
+        TRIANGLE_SEQUENCE[self.sequence as usize]
+    }
+}
+
+struct Noise {
+    enabled: bool,
+    envelope: Envelope,
+    // short mode taps bit 6 instead of bit 1
+    mode: bool,
+    periods: &'static [u16; 16],
+    timer_period: u16,
+    timer: u16,
+    // 15 bit linear feedback shift register
+    shift: u16,
+    length: u8,
+}
+
+impl Noise {
+    fn new(periods: &'static [u16; 16]) -> Self {
+        Noise {
+            enabled: false,
+            envelope: Envelope::new(),
+            mode: false,
+            periods: periods,
+            timer_period: periods[0] / 2,
+            timer: 0,
+            shift: 1,
+            length: 0,
+        }
+    }
+
+    fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            // --LC VVVV
+            0 => self.envelope.write(data),
+            // M--- PPPP. the table is in cpu cycles, the timer counts apu cycles.
+            2 => {
+                self.mode = data & 0b1000_0000 != 0;
+                self.timer_period = self.periods[(data & 0x0f) as usize] / 2;
+            }
+            // LLLL L---
+            3 => {
+                if self.enabled {
+                    self.length = LENGTH_TABLE[(data >> 3) as usize];
+                }
+                self.envelope.start = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.length = 0;
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+            let tap = if self.mode { 6 } else { 1 };
+            let feedback = (self.shift ^ (self.shift >> tap)) & 1;
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if self.length > 0 && !self.envelope.looping {
+            self.length -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.length == 0 || self.shift & 1 != 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+// delta modulation channel. plays 1 bit deltas read from $c000-$ffff,
+// fetching each byte over the cpu bus.
+struct Dmc {
+    irq_enabled: bool,
+    irq: bool,
+    looping: bool,
+    rates: &'static [u16; 16],
+    timer_period: u16,
+    timer: u16,
+    // 7 bit output level
+    level: u8,
+    sample_addr: u16,
+    sample_length: u16,
+    current_addr: u16,
+    bytes_remaining: u16,
+    buffer: Option<u8>,
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+}
+
+impl Dmc {
+    fn new(rates: &'static [u16; 16]) -> Self {
+        Dmc {
+            irq_enabled: false,
+            irq: false,
+            looping: false,
+            rates: rates,
+            timer_period: rates[0],
+            timer: 0,
+            level: 0,
+            sample_addr: 0xc000,
+            sample_length: 1,
+            current_addr: 0xc000,
+            bytes_remaining: 0,
+            buffer: None,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+        }
+    }
+
+    fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            // IL-- RRRR
+            0 => {
+                self.irq_enabled = data & 0b1000_0000 != 0;
+                self.looping = data & 0b0100_0000 != 0;
+                self.timer_period = self.rates[(data & 0x0f) as usize];
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+            // -DDD DDDD
+            1 => self.level = data & 0x7f,
+            // $c000 + A * 64
+            2 => self.sample_addr = 0xc000 | ((data as u16) << 6),
+            // L * 16 + 1 bytes
+            _ => self.sample_length = ((data as u16) << 4) | 1,
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_addr = self.sample_addr;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    fn fill(&mut self, data: u8) {
+        self.buffer = Some(data);
+        self.current_addr = self.current_addr.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period - 1;
+
+        if !self.silence {
+            if self.shift & 1 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+        self.shift >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.buffer.take() {
+                Some(data) => {
+                    self.silence = false;
+                    self.shift = data;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn length_counter() {
+        let mut apu = Apu::default();
+        // loads are ignored while the channel is disabled
+        apu.write_register(0x4003, 0b0000_1000);
+        assert_eq!(apu.read_status() & 1, 0);
+        apu.write_register(0x4015, 0b01);
+        apu.write_register(0x4003, 0b0000_1000);
+        assert_eq!(apu.pulse1.length, 254);
+        apu.half_frame();
+        assert_eq!(apu.pulse1.length, 253);
+        // the envelope loop flag halts it
+        apu.write_register(0x4000, 0b0010_0000);
+        apu.half_frame();
+        assert_eq!(apu.pulse1.length, 253);
+        assert_eq!(apu.read_status() & 1, 1);
+        // disabling clears it right away
+        apu.write_register(0x4015, 0);
+        assert_eq!(apu.read_status() & 1, 0);
+    }
+
+    #[test]
+    fn sweep_mute() {
+        let mut apu = Apu::default();
+        // periods below 8 are muted
+        apu.write_register(0x4002, 7);
+        assert!(apu.pulse1.muted());
+        apu.write_register(0x4002, 8);
+        assert!(!apu.pulse1.muted());
+        // an overflowing target mutes even with the sweep disabled
+        apu.write_register(0x4002, 0x00);
+        apu.write_register(0x4003, 0x06);
+        apu.write_register(0x4001, 0b0000_0001);
+        assert_eq!(apu.pulse1.sweep_target(), 0x900);
+        assert!(apu.pulse1.muted());
+        // negated, pulse 1 subtracts one more than pulse 2
+        for addr in [0x4001, 0x4005] {
+            apu.write_register(addr, 0b0000_1001);
+            apu.write_register(addr + 1, 0x00);
+            apu.write_register(addr + 2, 0x01);
+        }
+        assert_eq!(apu.pulse1.sweep_target(), 0x7f);
+        assert_eq!(apu.pulse2.sweep_target(), 0x80);
+        // an enabled sweep moves the period on the half frame
+        apu.write_register(0x4005, 0b1000_1001);
+        apu.half_frame();
+        assert_eq!(apu.pulse2.timer_period, 0x80);
+    }
+
+    #[test]
+    fn envelope() {
+        let mut apu = Apu::default();
+        // decay with a divider period of 2
+        apu.write_register(0x4000, 0b0000_0010);
+        apu.write_register(0x4003, 0);
+        apu.quarter_frame();
+        assert_eq!(apu.pulse1.envelope.output(), 15);
+        for _ in 0..3 {
+            apu.quarter_frame();
+        }
+        assert_eq!(apu.pulse1.envelope.output(), 14);
+        for _ in 0..14 * 3 {
+            apu.quarter_frame();
+        }
+        assert_eq!(apu.pulse1.envelope.output(), 0);
+        // without the loop flag it stays at 0
+        for _ in 0..3 {
+            apu.quarter_frame();
+        }
+        assert_eq!(apu.pulse1.envelope.output(), 0);
+        apu.write_register(0x4000, 0b0010_0010);
+        for _ in 0..3 {
+            apu.quarter_frame();
+        }
+        assert_eq!(apu.pulse1.envelope.output(), 15);
+        // constant volume ignores the decay level
+        apu.write_register(0x4000, 0b0001_0110);
+        assert_eq!(apu.pulse1.envelope.output(), 6);
+    }
+
+    #[test]
+    fn frame_sequencer_steps() {
+        let mut apu = Apu::default();
+        apu.write_register(0x4015, 0b01);
+        apu.write_register(0x4003, 0b0000_1000);
+        // first quarter frame on cycle 7457, first half frame on 14913
+        for _ in 0..FRAME_STEP_1 - 1 {
+            apu.tick();
+        }
+        assert!(apu.pulse1.envelope.start);
+        apu.tick();
+        assert!(!apu.pulse1.envelope.start);
+        for _ in FRAME_STEP_1..FRAME_STEP_2 - 1 {
+            apu.tick();
+        }
+        assert_eq!(apu.pulse1.length, 254);
+        apu.tick();
+        assert_eq!(apu.pulse1.length, 253);
+        // the 4-step sequence clocks the length counters twice per 29830 cycles
+        for _ in FRAME_STEP_2..FRAME_STEP_4 + 1 + FRAME_STEP_2 {
+            apu.tick();
+        }
+        assert_eq!(apu.pulse1.length, 251);
+    }
+
+    #[test]
+    fn five_step_mode() {
+        let mut apu = Apu::default();
+        apu.write_register(0x4015, 0b01);
+        apu.write_register(0x4003, 0b0000_1000);
+        // switching to 5-step mode clocks a half frame when the sequencer resets,
+        // 3 cycles after a write on an odd cycle
+        apu.write_register(0x4017, 0b1000_0000);
+        for _ in 0..2 {
+            apu.tick();
+        }
+        assert_eq!(apu.pulse1.length, 254);
+        apu.tick();
+        assert_eq!(apu.pulse1.length, 253);
+        // then half frames at 14913 and 37281, and never a frame irq
+        for _ in 0..FRAME_STEP_5 - 1 {
+            apu.tick();
+        }
+        assert_eq!(apu.pulse1.length, 252);
+        apu.tick();
+        assert_eq!(apu.pulse1.length, 251);
+        assert!(!apu.frame_irq());
+    }
+
+    #[test]
+    fn frame_irq_cleared_by_status_read() {
+        let mut apu = Apu::default();
+        for _ in 0..FRAME_STEP_4 - 2 {
+            apu.tick();
+        }
+        assert!(!apu.frame_irq());
+        apu.tick();
+        assert!(apu.frame_irq());
+        assert_eq!(apu.read_status() & 0x40, 0x40);
+        assert_eq!(apu.read_status() & 0x40, 0);
+        // set again on the next two cycles
+        apu.tick();
+        assert_eq!(apu.read_status() & 0x40, 0x40);
+
+        // inhibited, a whole sequence goes by without one
+        apu.write_register(0x4017, 0b0100_0000);
+        for _ in 0..2 * FRAME_STEP_4 {
+            apu.tick();
+        }
+        assert!(!apu.frame_irq());
+    }
+
+    #[test]
+    fn dmc_fetch_stalls_the_cpu() {
+        let mut apu = Apu::default();
+        // one byte sample at $c000
+        apu.write_register(0x4015, 0b1_0000);
+        assert_eq!(apu.dmc_request(), Some(0xc000));
+        assert_eq!(apu.dmc_fill(0xff), DMC_FETCH_STALL);
+        assert_eq!(apu.dmc_request(), None);
+
+        // lda #$10, sta $4015, nop. the fetch lands on the nop, which pays 4 more cycles.
+        let mut emu = crate::emu::Emu::default();
+        let code = [0xa9, 0x10, 0x8d, 0x15, 0x40, 0xea];
+        assert_eq!(crate::fixture::run(&mut emu, &code, 3), 2 + 4 + 2 + DMC_FETCH_STALL);
+        let mut emu = crate::emu::Emu::default();
+        let code = [0xa9, 0x00, 0x8d, 0x15, 0x40, 0xea];
+        assert_eq!(crate::fixture::run(&mut emu, &code, 3), 2 + 4 + 2);
+    }
+
+    #[test]
+    fn pal_tables() {
+        let mut apu = Apu::new(Timing::PAL);
+        assert_eq!(apu.mixer.cpu_clock(), 1_662_607);
+        apu.write_register(0x400e, 0x0f);
+        assert_eq!(apu.noise.timer_period, 3778 / 2);
+        apu.write_register(0x4010, 0x0f);
+        assert_eq!(apu.dmc.timer_period, 50);
+        // the frame irq comes at 33252 cycles instead of 29828
+        for _ in 0..FRAME_STEP_4 + 100 {
+            apu.tick();
+        }
+        assert!(!apu.frame_irq());
+        for _ in FRAME_STEP_4 + 100..33252 {
+            apu.tick();
+        }
+        assert!(apu.frame_irq());
+    }
+}
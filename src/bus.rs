@@ -1,17 +1,194 @@
+use crate::apu::Apu;
+use crate::cart::{Rom, Timing};
+use crate::joypad::Joypad;
+use crate::mapper::{Mapper, Nrom};
+use crate::ppu::Ppu;
+
+const RAM_SIZE: usize = 0x800;
+const CPU_RAM_ZERO: u16 = 0x0000;
+const CPU_RAM_ADDR_MASK: u16 = 0x07ff;
+const CPU_RAM_MIRROR_END: u16 = 0x1fff;
+const PPU_REG_ZERO: u16 = 0x2000;
+const PPU_REG_ADDR_MASK: u16 = 0x2007;
+const PPU_REG_MIRROR_END: u16 = 0x3fff;
+const APU_IO_ZERO: u16 = 0x4000;
+const APU_IO_END: u16 = 0x401f;
+const OAM_DMA: u16 = 0x4014;
+const APU_STATUS: u16 = 0x4015;
+const OAM_DATA: u16 = 0x2004;
+const JOYPAD1: u16 = 0x4016;
+const JOYPAD2: u16 = 0x4017;
+const CART_ZERO: u16 = 0x4020;
+const CART_END: u16 = 0xffff;
+
+// cpu address space. every access is routed to the device behind it, so
+// register reads and writes get their side effects.
 pub struct Bus {
-    cpu_vram: [u8; 2048],
-    prg_rom: Vec<u8>,
-    ppu: Ppu
+    pub ram: [u8; RAM_SIZE],
+    pub ppu: Ppu,
+    pub apu: Apu,
+    pub joypad1: Joypad,
+    pub joypad2: Joypad,
+    pub cart: Box<dyn Mapper>,
+    // last value driven on the data bus. unmapped reads return it.
+    open_bus: u8,
+    // cpu cycles already clocked into the devices during the current instruction
+    ticks: u32,
+    // page written to $4014. the copy runs once the writing instruction finishes.
+    dma_page: Option<u8>,
+    // cpu cycles stolen by dmc sample fetches, paid after the current instruction
+    stall: u32,
 }
 
-impl Bus {
-    pub fn new(rom: Rom) -> Self {
-        let ppu = NesPPU::new(rom.chr_rom, rom.screen_mirroring);
+impl Default for Bus {
+    fn default() -> Self {
+        Bus::new(Box::new(Nrom::new(Rom::nothing())))
+    }
+}
 
+impl Bus {
+    pub fn new(cart: Box<dyn Mapper>) -> Self {
         Bus {
-            cpu_vram: [0; 2048],
-            prg_rom: rom.prg_rom,
-            ppu: ppu,
+            ram: [0; RAM_SIZE],
+            ppu: Ppu::new(),
+            apu: Apu::new(Timing::NTSC),
+            joypad1: Joypad::new(),
+            joypad2: Joypad::new(),
+            cart: cart,
+            open_bus: 0,
+            ticks: 0,
+            dma_page: None,
+            stall: 0,
+        }
+    }
+
+    pub fn set_cart(&mut self, cart: Box<dyn Mapper>) {
+        self.ppu = Ppu::new();
+        self.cart = cart;
+    }
+
+    // one cpu cycle is one apu cycle and three ppu dots
+    pub fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            self.apu.tick();
+            if let Some(addr) = self.apu.dmc_request() {
+                let data = self.peek(addr);
+                self.stall += self.apu.dmc_fill(data);
+            }
+            self.cart.cpu_clock();
+            for _ in 0..3 {
+                self.ppu.tick(self.cart.as_mut());
+            }
+        }
+    }
+
+    // every bus access takes one cpu cycle. the devices are clocked before the
+    // access so register reads see the state of the cycle they happen on.
+    #[inline]
+    fn access_tick(&mut self) {
+        self.tick(1);
+        self.ticks += 1;
+    }
+
+    // called at the end of every instruction with the cycles it took.
+    // cycles without a bus access (internal operations) are clocked here.
+    pub fn catch_up(&mut self, cycles: u32) {
+        let rest = cycles.saturating_sub(self.ticks);
+        self.tick(rest);
+        self.ticks = 0;
+    }
+
+    pub fn read(&mut self, addr: u16) -> u8 {
+        self.access_tick();
+        let r = match addr {
+            CPU_RAM_ZERO..=CPU_RAM_MIRROR_END => {
+                self.ram[(addr & CPU_RAM_ADDR_MASK) as usize]
+            }
+            PPU_REG_ZERO..=PPU_REG_MIRROR_END => {
+                self.ppu.read_register(addr & PPU_REG_ADDR_MASK, self.cart.as_mut())
+            }
+            APU_STATUS => {
+                // bit 5 is not driven
+                (self.open_bus & 0x20) | self.apu.read_status()
+            }
+            JOYPAD1 => {
+                (self.open_bus & 0xe0) | self.joypad1.read()
+            }
+            JOYPAD2 => {
+                (self.open_bus & 0xe0) | self.joypad2.read()
+            }
+            APU_IO_ZERO..=APU_IO_END => {
+                self.open_bus
+            }
+            CART_ZERO..=CART_END => {
+                self.cart.cpu_read(addr).unwrap_or(self.open_bus)
+            }
+        };
+        self.open_bus = r;
+        r
+    }
+
+    // read without side effects, for debuggers and trace logs.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            CPU_RAM_ZERO..=CPU_RAM_MIRROR_END => {
+                self.ram[(addr & CPU_RAM_ADDR_MASK) as usize]
+            }
+            CART_ZERO..=CART_END => {
+                self.cart.cpu_read(addr).unwrap_or(self.open_bus)
+            }
+            _ => {
+                self.open_bus
+            }
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        self.access_tick();
+        self.open_bus = data;
+        match addr {
+            CPU_RAM_ZERO..=CPU_RAM_MIRROR_END => {
+                self.ram[(addr & CPU_RAM_ADDR_MASK) as usize] = data;
+            }
+            PPU_REG_ZERO..=PPU_REG_MIRROR_END => {
+                self.ppu.write_register(addr & PPU_REG_ADDR_MASK, data, self.cart.as_mut());
+            }
+            OAM_DMA => {
+                self.dma_page = Some(data);
+            }
+            JOYPAD1 => {
+                self.joypad1.write(data);
+                self.joypad2.write(data);
+            }
+            APU_IO_ZERO..=APU_IO_END => {
+                self.apu.write_register(addr, data);
+            }
+            CART_ZERO..=CART_END => {
+                self.cart.cpu_write(addr, data);
+            }
+        }
+    }
+
+    pub fn take_stall(&mut self) -> u32 {
+        std::mem::take(&mut self.stall)
+    }
+
+    pub fn take_dma(&mut self) -> Option<u8> {
+        self.dma_page.take()
+    }
+
+    // copy $xx00-$xxff to oam through $2004. the cpu is halted for one cycle,
+    // plus one more to align when the dma starts on an odd cycle, then each
+    // byte takes a read and a write cycle. returns the cycles the cpu was stalled.
+    pub fn oam_dma(&mut self, page: u8, odd_cycle: bool) -> u32 {
+        let wait = if odd_cycle { 2 } else { 1 };
+        self.tick(wait);
+        let base = (page as u16) << 8;
+        for i in 0..0x100 {
+            let data = self.read(base | i);
+            self.write(OAM_DATA, data);
         }
+        self.ticks = 0;
+        wait + 512
     }
 }
@@ -1,18 +1,106 @@
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Mirroring {
    VERTICAL,
    HORIZONTAL,
    FOUR_SCREEN,
+   // every nametable shows the first or the second 1K of vram
+   SINGLE_SCREEN_LOWER,
+   SINGLE_SCREEN_UPPER,
+}
+
+// cpu/ppu timing the rom was made for
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Timing {
+   NTSC,
+   PAL,
+   // runs on more than one region
+   MULTIPLE,
+   DENDY,
+}
+
+impl Timing {
+    // cpu clock in Hz. multi-region roms run at the ntsc clock.
+    pub fn cpu_clock(&self) -> u32 {
+        match self {
+            Timing::PAL => 1_662_607,
+            Timing::DENDY => 1_773_448,
+            Timing::NTSC | Timing::MULTIPLE => 1_789_773,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ConsoleType {
+   NES,
+   // byte 13 holds the vs. ppu type (low nibble) and hardware type (high nibble)
+   VS_SYSTEM(u8),
+   PLAYCHOICE_10,
+   // extended console type from byte 13
+   EXTENDED(u8),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RomError {
+   // no "NES\x1a" signature
+   BAD_MAGIC,
+   // the file is shorter than the 16 byte header
+   TRUNCATED_HEADER,
+   TRUNCATED_TRAINER,
+   TRUNCATED_PRG { expected: usize, found: usize },
+   TRUNCATED_CHR { expected: usize, found: usize },
+   NO_PRG_ROM,
+   UNSUPPORTED_MAPPER(u16),
+   UNSUPPORTED_FORMAT(&'static str),
+}
+
+impl std::fmt::Display for RomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RomError::BAD_MAGIC => write!(f, "file is not in iNES file format"),
+            RomError::TRUNCATED_HEADER => write!(f, "file is too short for an iNES header"),
+            RomError::TRUNCATED_TRAINER => write!(f, "trainer is truncated"),
+            RomError::TRUNCATED_PRG { expected, found } => {
+                write!(f, "prg rom is truncated: expected {} bytes, found {}", expected, found)
+            }
+            RomError::TRUNCATED_CHR { expected, found } => {
+                write!(f, "chr rom is truncated: expected {} bytes, found {}", expected, found)
+            }
+            RomError::NO_PRG_ROM => write!(f, "header declares no prg rom"),
+            RomError::UNSUPPORTED_MAPPER(n) => write!(f, "mapper {} is not supported", n),
+            RomError::UNSUPPORTED_FORMAT(why) => write!(f, "unsupported rom format: {}", why),
+        }
+    }
 }
 
+impl std::error::Error for RomError {}
+
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
 const PRG_ROM_PAGE_SIZE: usize = 16384;
 const CHR_ROM_PAGE_SIZE: usize = 8192;
+const PRG_RAM_PAGE_SIZE: usize = 8192;
 
 pub struct Rom {
    pub prg_rom: Vec<u8>,
    pub chr_rom: Vec<u8>,
-   pub mapper: u8,
+   // 512 bytes meant to be loaded at $7000
+   pub trainer: Option<Vec<u8>>,
+   // 12 bit mapper number. ines 1.0 only has 8 bits.
+   pub mapper: u16,
+   pub submapper: u8,
    pub screen_mirroring: Mirroring,
+   // battery backed memory present
+   pub battery: bool,
+   pub nes2: bool,
+   // sizes in bytes. nvram is the battery backed part.
+   pub prg_ram_size: usize,
+   pub prg_nvram_size: usize,
+   pub chr_ram_size: usize,
+   pub chr_nvram_size: usize,
+   pub timing: Timing,
+   pub console_type: ConsoleType,
+   // default expansion device id, see the nesdev wiki list
+   pub expansion_device: u8,
 }
 
 impl Rom {
@@ -20,22 +108,39 @@ impl Rom {
         Rom {
             prg_rom: vec![],
             chr_rom: vec![],
+            trainer: None,
             mapper: 0,
+            submapper: 0,
             screen_mirroring: Mirroring::HORIZONTAL,
+            battery: false,
+            nes2: false,
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            timing: Timing::NTSC,
+            console_type: ConsoleType::NES,
+            expansion_device: 0,
         }
     }
 
-    pub fn from_raw(raw: &Vec<u8>) -> Result<Rom, String> {
-        if &raw[0..4] != [0x4e, 0x45, 0x53, 0x1a] {
-            return Err("File is not in iNES file format".to_string());
+    pub fn from_raw(raw: &[u8]) -> Result<Rom, RomError> {
+        if raw.len() < 4 || raw[0..4] != [0x4e, 0x45, 0x53, 0x1a] {
+            return Err(RomError::BAD_MAGIC);
+        }
+        if raw.len() < HEADER_SIZE {
+            return Err(RomError::TRUNCATED_HEADER);
         }
  
-        let mapper = (raw[7] & 0b1111_0000) | (raw[6] >> 4);
- 
-        let ines_ver = (raw[7] >> 2) & 0b11;
-        if ines_ver != 0 {
-            return Err("NES2.0 format is not supported".to_string());
+        let nes2 = (raw[7] >> 2) & 0b11 == 2;
+        // some old dumpers wrote garbage like "DiskDude!" over bytes 7-15.
+        // an ines 1.0 header with non-zero padding only keeps the low nibble of the mapper.
+        let dirty = !nes2 && raw[12..16].iter().any(|&b| b != 0);
+        let mut mapper = (raw[6] >> 4) as u16;
+        if !dirty {
+            mapper |= (raw[7] & 0b1111_0000) as u16;
         }
+        let battery = raw[6] & 0b10 != 0;
  
         let four_screen = raw[6] & 0b1000 != 0;
         let vertical_mirroring = raw[6] & 0b1 != 0;
@@ -45,19 +150,250 @@ impl Rom {
             (false, false) => Mirroring::HORIZONTAL,
         };
  
-        let prg_rom_size = raw[4] as usize * PRG_ROM_PAGE_SIZE;
-        let chr_rom_size = raw[5] as usize * CHR_ROM_PAGE_SIZE;
+        let (prg_rom_size, chr_rom_size) = if nes2 {
+            (nes2_rom_size(raw[4], raw[9] & 0x0f, PRG_ROM_PAGE_SIZE),
+             nes2_rom_size(raw[5], raw[9] >> 4, CHR_ROM_PAGE_SIZE))
+        } else {
+            (raw[4] as usize * PRG_ROM_PAGE_SIZE, raw[5] as usize * CHR_ROM_PAGE_SIZE)
+        };
  
-        let skip_trainer = raw[6] & 0b100 != 0;
+        if prg_rom_size == 0 {
+            return Err(RomError::NO_PRG_ROM);
+        }
+
+        let has_trainer = raw[6] & 0b100 != 0;
  
-        let prg_rom_start = 16 + if skip_trainer { 512 } else { 0 };
-        let chr_rom_start = prg_rom_start + prg_rom_size;
+        let prg_rom_start = HEADER_SIZE + if has_trainer { TRAINER_SIZE } else { 0 };
+        if raw.len() < prg_rom_start {
+            return Err(RomError::TRUNCATED_TRAINER);
+        }
+        // nes 2.0 exponent sizes can be huge, so the ends are checked for overflow
+        let chr_rom_start = match prg_rom_start.checked_add(prg_rom_size) {
+            Some(end) if end <= raw.len() => end,
+            _ => return Err(RomError::TRUNCATED_PRG { expected: prg_rom_size, found: raw.len() - prg_rom_start }),
+        };
+        let chr_rom_end = match chr_rom_start.checked_add(chr_rom_size) {
+            Some(end) if end <= raw.len() => end,
+            _ => return Err(RomError::TRUNCATED_CHR { expected: chr_rom_size, found: raw.len() - chr_rom_start }),
+        };
  
-        Ok(Rom {
-            prg_rom: raw[prg_rom_start..(prg_rom_start + prg_rom_size)].to_vec(),
-            chr_rom: raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec(),
+        let mut rom = Rom {
+            prg_rom: raw[prg_rom_start..chr_rom_start].to_vec(),
+            chr_rom: raw[chr_rom_start..chr_rom_end].to_vec(),
+            trainer: if has_trainer { Some(raw[HEADER_SIZE..prg_rom_start].to_vec()) } else { None },
             mapper: mapper,
+            submapper: 0,
             screen_mirroring: screen_mirroring,
-        })
+            battery: battery,
+            nes2: nes2,
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            timing: Timing::NTSC,
+            console_type: ConsoleType::NES,
+            expansion_device: 0,
+        };
+        if nes2 {
+            rom.parse_nes2(raw);
+        } else {
+            // no chr rom means the board has 8K of chr ram
+            if chr_rom_size == 0 {
+                rom.chr_ram_size = CHR_ROM_PAGE_SIZE;
+            }
+            // byte 8 is prg ram in 8K units, 0 meaning 8K for compatibility
+            let prg_ram = (raw[8].max(1) as usize) * PRG_RAM_PAGE_SIZE;
+            if battery {
+                rom.prg_nvram_size = prg_ram;
+            } else {
+                rom.prg_ram_size = prg_ram;
+            }
+            if !dirty && raw[9] & 1 != 0 {
+                rom.timing = Timing::PAL;
+            }
+            rom.console_type = match raw[7] & 0b11 {
+                1 => ConsoleType::VS_SYSTEM(0),
+                2 => ConsoleType::PLAYCHOICE_10,
+                _ => ConsoleType::NES,
+            };
+        }
+        Ok(rom)
+    }
+
+    // bytes 8-15 of a nes 2.0 header
+    fn parse_nes2(&mut self, raw: &[u8]) {
+        self.mapper |= ((raw[8] & 0x0f) as u16) << 8;
+        self.submapper = raw[8] >> 4;
+        self.prg_ram_size = shift_size(raw[10] & 0x0f);
+        self.prg_nvram_size = shift_size(raw[10] >> 4);
+        self.chr_ram_size = shift_size(raw[11] & 0x0f);
+        self.chr_nvram_size = shift_size(raw[11] >> 4);
+        self.timing = match raw[12] & 0b11 {
+            0 => Timing::NTSC,
+            1 => Timing::PAL,
+            2 => Timing::MULTIPLE,
+            _ => Timing::DENDY,
+        };
+        self.console_type = match raw[7] & 0b11 {
+            0 => ConsoleType::NES,
+            1 => ConsoleType::VS_SYSTEM(raw[13]),
+            2 => ConsoleType::PLAYCHOICE_10,
+            _ => ConsoleType::EXTENDED(raw[13] & 0x0f),
+        };
+        self.expansion_device = raw[15] & 0b0011_1111;
     }
- }
\ No newline at end of file
+ }
+
+// nes 2.0 rom size from the lsb byte and the msb nibble. an msb of $f switches
+// the lsb to exponent-multiplier form: 2^E * (MM*2+1) bytes, EEEEEEMM.
+fn nes2_rom_size(lsb: u8, msb: u8, unit: usize) -> usize {
+    if msb == 0x0f {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0b11) as usize * 2 + 1;
+        2usize.checked_pow(exponent).map_or(usize::MAX, |n| n.saturating_mul(multiplier))
+    } else {
+        (((msb as usize) << 8) | lsb as usize) * unit
+    }
+}
+
+// nes 2.0 ram sizes are stored as a shift count: 64 << n bytes, 0 meaning none
+fn shift_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::image;
+
+    // header plus zeroed prg/chr of the sizes the header asks for
+    fn ines(prg_banks: u8, chr_banks: u8, flags6: u8, flags7: u8) -> Vec<u8> {
+        let size = prg_banks as usize * PRG_ROM_PAGE_SIZE + chr_banks as usize * CHR_ROM_PAGE_SIZE;
+        image(HEADER_SIZE, &[(0, b"NES\x1a"), (4, &[prg_banks, chr_banks, flags6, flags7])], &vec![0; size])
+    }
+
+    #[test]
+    fn ines_header() {
+        let mut raw = ines(2, 1, 0x13, 0x40);
+        raw[16] = 0xaa;
+        let rom = Rom::from_raw(&raw).unwrap();
+        assert_eq!(rom.mapper, 0x41);
+        assert_eq!(rom.screen_mirroring, Mirroring::VERTICAL);
+        assert!(rom.battery);
+        assert!(!rom.nes2);
+        assert_eq!(rom.prg_rom.len(), 2 * PRG_ROM_PAGE_SIZE);
+        assert_eq!(rom.prg_rom[0], 0xaa);
+        assert_eq!(rom.chr_rom.len(), CHR_ROM_PAGE_SIZE);
+        assert_eq!(rom.prg_nvram_size, PRG_RAM_PAGE_SIZE);
+        assert_eq!(rom.chr_ram_size, 0);
+    }
+
+    #[test]
+    fn dirty_header_drops_upper_mapper_nibble() {
+        let mut raw = ines(1, 0, 0x10, 0x40);
+        raw[12..16].copy_from_slice(b"Dude");
+        let rom = Rom::from_raw(&raw).unwrap();
+        assert_eq!(rom.mapper, 0x01);
+        assert_eq!(rom.chr_ram_size, CHR_ROM_PAGE_SIZE);
+    }
+
+    #[test]
+    fn trainer_is_split_off() {
+        let mut raw = ines(1, 0, 0b100, 0);
+        raw.splice(HEADER_SIZE..HEADER_SIZE, vec![0x55; TRAINER_SIZE]);
+        let rom = Rom::from_raw(&raw).unwrap();
+        assert_eq!(rom.trainer, Some(vec![0x55; TRAINER_SIZE]));
+        assert_eq!(rom.prg_rom, vec![0; PRG_ROM_PAGE_SIZE]);
+    }
+
+    #[test]
+    fn nes2_header() {
+        let mut raw = ines(1, 1, 0x08, 0x28);
+        raw[8] = 0x52; // submapper 5, mapper bits 8-11 = 2
+        raw[10] = 0x70; // 8K prg nvram
+        raw[11] = 0x07; // 8K chr ram
+        raw[12] = 1;
+        raw[15] = 0x41;
+        let rom = Rom::from_raw(&raw).unwrap();
+        assert!(rom.nes2);
+        assert_eq!(rom.mapper, 0x220);
+        assert_eq!(rom.submapper, 5);
+        assert_eq!(rom.screen_mirroring, Mirroring::FOUR_SCREEN);
+        assert_eq!(rom.prg_ram_size, 0);
+        assert_eq!(rom.prg_nvram_size, 0x2000);
+        assert_eq!(rom.chr_ram_size, 0x2000);
+        assert_eq!(rom.timing, Timing::PAL);
+        assert_eq!(rom.expansion_device, 0x01);
+
+        for (byte, timing) in [(0, Timing::NTSC), (2, Timing::MULTIPLE), (3, Timing::DENDY)] {
+            raw[12] = byte;
+            assert_eq!(Rom::from_raw(&raw).unwrap().timing, timing);
+        }
+    }
+
+    #[test]
+    fn nes2_rom_sizes() {
+        assert_eq!(nes2_rom_size(2, 0, PRG_ROM_PAGE_SIZE), 2 * PRG_ROM_PAGE_SIZE);
+        assert_eq!(nes2_rom_size(0x00, 1, CHR_ROM_PAGE_SIZE), 0x100 * CHR_ROM_PAGE_SIZE);
+        // exponent-multiplier form: 2^E * (MM*2+1)
+        assert_eq!(nes2_rom_size(0b001010_00, 0x0f, PRG_ROM_PAGE_SIZE), 1024);
+        assert_eq!(nes2_rom_size(0b000100_01, 0x0f, PRG_ROM_PAGE_SIZE), 16 * 3);
+        assert_eq!(nes2_rom_size(0b000000_11, 0x0f, PRG_ROM_PAGE_SIZE), 7);
+        assert_eq!(nes2_rom_size(0xff, 0x0f, PRG_ROM_PAGE_SIZE), usize::MAX);
+        assert_eq!(shift_size(0), 0);
+        assert_eq!(shift_size(7), 0x2000);
+    }
+
+    #[test]
+    fn exponent_prg_size() {
+        let mut raw = ines(0, 0, 0, 0x08);
+        raw[4] = 0b001010_00;
+        raw[9] = 0x0f;
+        raw.resize(HEADER_SIZE + 1024, 0);
+        let rom = Rom::from_raw(&raw).unwrap();
+        assert_eq!(rom.prg_rom.len(), 1024);
+        assert!(rom.chr_rom.is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Rom::from_raw(&[0x4e, 0x45, 0x53]).err(), Some(RomError::BAD_MAGIC));
+        assert_eq!(Rom::from_raw(&[0; 16]).err(), Some(RomError::BAD_MAGIC));
+        assert_eq!(Rom::from_raw(&[0x4e, 0x45, 0x53, 0x1a, 1]).err(), Some(RomError::TRUNCATED_HEADER));
+        assert_eq!(Rom::from_raw(&ines(0, 0, 0, 0)).err(), Some(RomError::NO_PRG_ROM));
+
+        let mut raw = ines(1, 0, 0b100, 0);
+        raw.truncate(HEADER_SIZE + 100);
+        assert_eq!(Rom::from_raw(&raw).err(), Some(RomError::TRUNCATED_TRAINER));
+
+        let mut raw = ines(2, 1, 0, 0);
+        raw.truncate(HEADER_SIZE + PRG_ROM_PAGE_SIZE);
+        assert_eq!(Rom::from_raw(&raw).err(),
+                   Some(RomError::TRUNCATED_PRG { expected: 2 * PRG_ROM_PAGE_SIZE, found: PRG_ROM_PAGE_SIZE }));
+
+        let mut raw = ines(1, 1, 0, 0);
+        raw.truncate(raw.len() - 1);
+        assert_eq!(Rom::from_raw(&raw).err(),
+                   Some(RomError::TRUNCATED_CHR { expected: CHR_ROM_PAGE_SIZE, found: CHR_ROM_PAGE_SIZE - 1 }));
+
+        // sizes that overflow usize are reported as truncated instead of panicking
+        let mut raw = ines(1, 0, 0, 0x08);
+        raw[4] = 0xff;
+        raw[9] = 0x0f;
+        assert_eq!(Rom::from_raw(&raw).err(),
+                   Some(RomError::TRUNCATED_PRG { expected: usize::MAX, found: PRG_ROM_PAGE_SIZE }));
+        let mut raw = ines(1, 0, 0, 0x08);
+        raw[5] = 0xff;
+        raw[9] = 0xf0;
+        assert_eq!(Rom::from_raw(&raw).err(),
+                   Some(RomError::TRUNCATED_CHR { expected: usize::MAX, found: 0 }));
+
+        let raw = ines(1, 0, 0xf0, 0xf0);
+        let rom = Rom::from_raw(&raw).unwrap();
+        assert_eq!(crate::mapper::from_rom(rom).err(), Some(RomError::UNSUPPORTED_MAPPER(0xff)));
+    }
+}
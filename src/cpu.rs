@@ -6,6 +6,8 @@ use crate::emu::Emu;
 use crate::ops::OP_CYCLE;
 use crate::ops::OP_FUNC;
 use crate::ops::OP_NAME;
+use crate::ops::OP_PAGE_CYCLE;
+use crate::ops::interrupt;
 //use crate::ops::OP_NAME;
 
 bitflags! {
@@ -21,6 +23,20 @@ bitflags! {
     }
 }
 
+bitflags! {
+    // devices that can hold the /IRQ line low. the line stays asserted
+    // as long as any of them is set.
+    pub struct IrqSource: u8 {
+        const APU_FRAME = 0b00000001;
+        const DMC       = 0b00000010;
+        const MAPPER    = 0b00000100;
+    }
+}
+
+pub const NMI_VECTOR: u16 = 0xFFFA;
+pub const RESET_VECTOR: u16 = 0xFFFC;
+pub const IRQ_VECTOR: u16 = 0xFFFE;
+
 pub struct Cpu {
     pub pc: u16,
     pub sp: Flags,
@@ -28,6 +44,11 @@ pub struct Cpu {
     pub x: u8,
     pub y: u8,
     pub s: u8,
+    pub jammed: bool,
+    pub page_crossed: bool,
+    nmi_line: bool,
+    nmi_pending: bool,
+    irq_line: IrqSource,
 }
 
 
@@ -57,24 +78,49 @@ impl Cpu {
     pub fn stack_ptr(&self) -> u16 {
         self.s as u16 + 0x0100
     }
+
+    // nmi is edge triggered: only the transition to asserted latches a request.
+    pub fn set_nmi(&mut self, level: bool) {
+        if level && !self.nmi_line {
+            self.nmi_pending = true;
+        }
+        self.nmi_line = level;
+    }
+    // irq is level triggered: it is serviced for as long as a source holds it.
+    pub fn set_irq(&mut self, source: IrqSource, level: bool) {
+        self.irq_line.set(source, level);
+    }
+    pub fn irq_asserted(&self) -> bool {
+        !self.irq_line.is_empty()
+    }
+    fn poll_interrupt(&mut self) -> Option<u16> {
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            Some(NMI_VECTOR)
+        } else if self.irq_asserted() && !self.sp.contains(Flags::I) {
+            Some(IRQ_VECTOR)
+        } else {
+            None
+        }
+    }
 }
 
 impl Emu {
     pub fn cpuinfo(emu: &Emu) {
-        let op = emu.mem.load_u8(emu.cpu.pc);
+        let op = emu.mem.peek_u8(emu.cpu.pc);
         info!("CPU at{:04x}: {} {:02x} {:02x} {:02x} stat: A:{:02x} X:{:02x} Y:{:02x} FLAG:{:02x} S:{:02x}",
-            emu.cpu.pc, OP_NAME[op as usize], op, emu.mem.load_u8(emu.cpu.pc+1),emu.mem.load_u8(emu.cpu.pc+2),
+            emu.cpu.pc, OP_NAME[op as usize], op, emu.mem.peek_u8(emu.cpu.pc+1),emu.mem.peek_u8(emu.cpu.pc+2),
             emu.cpu.a, emu.cpu.x, emu.cpu.y, emu.cpu.sp.bits(), emu.cpu.s);
     }
     
     pub fn cpudebug_for_r(emu: &Emu) {
-        let op = emu.mem.load_u8(emu.cpu.pc);
+        let op = emu.mem.peek_u8(emu.cpu.pc);
         debug!("{:04x} {} {:02x} A:{:02x} X:{:02x} Y:{:02x} P:{:02x} SP:{:02x} CYC: {}",
         emu.cpu.pc, OP_NAME[op as usize], op, emu.cpu.a, emu.cpu.x, emu.cpu.y, emu.cpu.sp.bits(), emu.cpu.s, emu.stat.cycle_counter);
     }
     
     pub fn cpudebug_for_test(emu: &Emu) {
-        let op = emu.mem.load_u8(emu.cpu.pc);
+        let op = emu.mem.peek_u8(emu.cpu.pc);
         debug!("{:04X} {:02X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
         emu.cpu.pc, op, emu.cpu.a, emu.cpu.x, emu.cpu.y, emu.cpu.sp.bits(), emu.cpu.s);
     }
@@ -86,12 +132,66 @@ impl Emu {
         }
     }
 
+    pub fn reset(&mut self) {
+        self.cpu = Cpu::default();
+        self.cpu.pc = self.mem.peek_u16(RESET_VECTOR);
+        self.stat.cycle_counter = 7;
+    }
+
     pub fn run_cpu_once(&mut self) {
+        let start = self.stat.cycle_counter;
+        self.execute_once();
+        // the bus has already clocked the ppu for every access the instruction made.
+        // clock the rest of the cycles the instruction took.
+        self.mem.bus.catch_up(self.stat.cycle_counter - start);
+        // a write to $4014 halts the cpu for 513 or 514 cycles after the instruction
+        if let Some(page) = self.mem.bus.take_dma() {
+            let odd = self.stat.cycle_counter % 2 == 1;
+            self.stat.cycle_counter += self.mem.bus.oam_dma(page, odd);
+        }
+        // dmc fetches halt the cpu too. the halted cycles can start more fetches.
+        loop {
+            let stall = self.mem.bus.take_stall();
+            if stall == 0 {
+                break;
+            }
+            self.mem.bus.tick(stall);
+            self.stat.cycle_counter += stall;
+        }
+        // the ppu reports each rising edge of its /nmi output, since the output can
+        // go up and down again within one instruction.
+        if self.mem.bus.ppu.take_nmi() {
+            self.cpu.set_nmi(true);
+            self.cpu.set_nmi(false);
+        }
+        let irq = self.mem.bus.cart.irq();
+        self.cpu.set_irq(IrqSource::MAPPER, irq);
+        let irq = self.mem.bus.apu.frame_irq();
+        self.cpu.set_irq(IrqSource::APU_FRAME, irq);
+        let irq = self.mem.bus.apu.dmc_irq();
+        self.cpu.set_irq(IrqSource::DMC, irq);
+    }
+
+    fn execute_once(&mut self) {
+        // a jammed cpu keeps fetching the kil opcode and ignores interrupts until reset.
+        if self.cpu.jammed {
+            self.stat.cycle_counter += OP_CYCLE[0x02] as u32;
+            return;
+        }
+        if let Some(vector) = self.cpu.poll_interrupt() {
+            interrupt(self, vector);
+            self.stat.cycle_counter += 7;
+            return;
+        }
         let op = self.mem.load_u8(self.cpu.pc);
         let f = OP_FUNC[op as usize];
         let c = OP_CYCLE[op as usize];
+        self.cpu.page_crossed = false;
         f(self);
         self.stat.cycle_counter += c as u32;
+        if self.cpu.page_crossed {
+            self.stat.cycle_counter += OP_PAGE_CYCLE[op as usize] as u32;
+        }
     }
     pub fn run_cpu_clocks(&mut self, clocks: u32) {
         let c = self.stat.cycle_counter;
@@ -110,6 +210,11 @@ impl Default for Cpu {
             x: 0x00,
             y: 0x00,
             s: 0xfd,
+            jammed: false,
+            page_crossed: false,
+            nmi_line: false,
+            nmi_pending: false,
+            irq_line: IrqSource::empty(),
         }
     }
 }
@@ -19,7 +19,7 @@ use rand::prelude::*;
 impl Mem {
     #[inline]
     pub fn get_frame_easy(&self) -> &[u8] {
-        &self.mem[0x200..0x600]
+        &self.bus.ram[0x200..0x600]
     }
 }
 
@@ -112,7 +112,7 @@ impl Emu {
                     use pretty_hex::*;
                     //Emu::cpuinfo(&self);
                     let mut file = std::fs::File::create("foo.txt").unwrap();
-                    file.write(format!("{:?}", self.mem.mem[0x000..0x600].hex_dump()).to_string().as_bytes()).expect("FUCKWRITER");
+                    file.write(format!("{:?}", self.mem.bus.ram[0x000..0x600].hex_dump()).to_string().as_bytes()).expect("FUCKWRITER");
                     control_flow.set_exit();
                 }
             }
@@ -1,9 +1,17 @@
-use crate::cart::Rom;
+use crate::apu::Apu;
+use crate::cart::{Rom, RomError, Timing};
 use crate::cpu::Cpu;
+use crate::frame::Frame;
+use crate::joypad::JoypadButton;
+use crate::mapper;
 use crate::mem::Mem;
-use crate::ppu::Ppu;
+use crate::mixer::{Mixer, SampleSink};
+use crate::save::SaveFile;
+use device_query::{DeviceQuery, DeviceState, Keycode};
 
-use log::debug;
+use log::{debug, error};
+use std::io;
+use std::path::PathBuf;
 use std::thread::sleep;
 use std::time::{Duration, Instant};
 use winit::{
@@ -15,15 +23,26 @@ use winit::{
 
 use pixels::{PixelsBuilder, SurfaceTexture};
 
-const WIDTH: u32 = 32;
-const HEIGHT: u32 = 32;
-const SCALE: f64 = 30.0;
+const WIDTH: u32 = Frame::WIDTH as u32;
+const HEIGHT: u32 = Frame::HEIGHT as u32;
+const SCALE: f64 = 3.0;
+
+const KEYMAP: [(Keycode, JoypadButton); 8] = [
+    (Keycode::Down, JoypadButton::DOWN),
+    (Keycode::Up, JoypadButton::UP),
+    (Keycode::Right, JoypadButton::RIGHT),
+    (Keycode::Left, JoypadButton::LEFT),
+    (Keycode::Space, JoypadButton::SELECT),
+    (Keycode::Enter, JoypadButton::START),
+    (Keycode::A, JoypadButton::BUTTON_A),
+    (Keycode::S, JoypadButton::BUTTON_B),
+];
 
 pub struct Emu {
     pub stat: Stat,
     pub cpu: Cpu,
-    pub ppu: Ppu,
     pub mem: Mem,
+    pub save: Option<SaveFile>,
 }
 
 pub struct Stat {
@@ -34,12 +53,11 @@ pub struct Stat {
 
 impl Default for Emu {
     fn default() -> Self {
-        let mem = Mem::new();
         Emu {
             stat: Stat::new(),
             cpu: Cpu::default(),
-            ppu: Ppu::new( Box::new(mem)),
-            mem,
+            mem: Mem::new(),
+            save: None,
         }
     }
 }
@@ -55,21 +73,151 @@ impl Stat {
 }
 
 impl Emu {
-    pub fn load(&mut self, bin: Vec<u8>) {
-        match Rom::from_raw(&bin) {
-            Ok(rom) => {
-                self.mem.set_rom(rom);
-            }
-            Err(e) => {
-                panic!("loading rom error. {}", e);
+    pub fn load(&mut self, bin: Vec<u8>) -> Result<(), RomError> {
+        let rom = Rom::from_raw(&bin)?;
+        let trainer = rom.trainer.clone();
+        let timing = rom.timing;
+        let mut cart = mapper::from_rom(rom)?;
+        if let Some(trainer) = trainer {
+            match cart.prg_ram() {
+                Some(ram) if ram.len() >= 0x1200 => ram[0x1000..0x1200].copy_from_slice(&trainer),
+                _ => debug!("no prg ram at $7000 for the trainer"),
             }
         }
+        self.mem.set_cart(cart);
+        self.set_timing(timing);
+        self.reset();
+        Ok(())
+    }
+
+    // persist battery backed prg ram to `path`. the current contents of the
+    // file are loaded right away. carts without a battery are left alone.
+    pub fn attach_save(&mut self, path: PathBuf, interval: Duration) -> io::Result<()> {
+        if !self.mem.bus.cart.battery() {
+            return Ok(());
+        }
+        let mut save = SaveFile::new(path, interval);
+        if let Some(ram) = self.mem.bus.cart.prg_ram() {
+            save.load(ram)?;
+        }
+        self.save = Some(save);
+        Ok(())
+    }
+
+    pub fn flush_save(&mut self) -> io::Result<()> {
+        match (self.save.as_mut(), self.mem.bus.cart.prg_ram()) {
+            (Some(save), Some(ram)) => save.flush(ram),
+            _ => Ok(()),
+        }
+    }
+
+    // give the apu the tables and the mixer the cpu clock of the console
+    // `timing` names. the host sample rate is kept.
+    pub fn set_timing(&mut self, timing: Timing) {
+        let rate = self.mem.bus.apu.mixer.sample_rate();
+        self.mem.bus.apu = Apu::new(timing);
+        self.set_sample_rate(rate);
+    }
+
+    // host sample rate of the audio output, e.g. 44100 or 48000
+    pub fn set_sample_rate(&mut self, rate: u32) {
+        let clock = self.mem.bus.apu.mixer.cpu_clock();
+        self.mem.bus.apu.mixer = Mixer::new(rate, clock);
+    }
+
+    // move the audio produced since the last call into `sink`.
+    // called once per frame this yields about rate / 60 samples.
+    pub fn drain_samples(&mut self, sink: &mut dyn SampleSink) {
+        self.mem.bus.apu.mixer.drain(sink);
     }
 
     pub fn memory_map_to_foo(&self) {
         use pretty_hex::*;
         use std::io::Write;
         let mut file = std::fs::File::create("foo.txt").unwrap();
-        file.write(format!("{:?}", self.mem.mem[0x000..0xffff].hex_dump()).to_string().as_bytes()).expect("FUCKWRITER");
+        let map: Vec<u8> = (0x0000..0xffff).map(|addr| self.mem.peek_u8(addr)).collect();
+        file.write(format!("{:?}", map.hex_dump()).to_string().as_bytes()).expect("FUCKWRITER");
+    }
+
+    // run until the ppu enters vblank
+    pub fn run_frame(&mut self) {
+        while !self.mem.bus.ppu.frame_complete {
+            self.run_cpu_once();
+        }
+        self.mem.bus.ppu.frame_complete = false;
+        self.stat.frame_counter += 1;
+    }
+
+    pub fn run(mut self) {
+        let event_loop = EventLoop::new();
+        let window = {
+            let size = LogicalSize::new(WIDTH as f64, HEIGHT as f64);
+            let scaled_size = LogicalSize::new(WIDTH as f64 * SCALE, HEIGHT as f64 * SCALE);
+            WindowBuilder::new()
+                .with_title("nesnes")
+                .with_inner_size(scaled_size)
+                .with_min_inner_size(size)
+                .build(&event_loop)
+                .unwrap()
+        };
+
+        let mut pixels = {
+            let window_size = window.inner_size();
+            let surface_texture =
+                SurfaceTexture::new(window_size.width, window_size.height, &window);
+            PixelsBuilder::new(WIDTH, HEIGHT, surface_texture)
+                .build()
+                .unwrap()
+        };
+
+        debug!("frame len {}", pixels.frame().len());
+
+        let device_state = DeviceState::new();
+        let mut last_time = Instant::now();
+        let mut time_acc = 0.0f32;
+
+        const FRAME_TIME: f32 = 1.0 / 60.0;
+
+        event_loop.run(move |event, _, control_flow| {
+            control_flow.set_poll();
+            time_acc += last_time.elapsed().as_secs_f32();
+            last_time = Instant::now();
+            if time_acc >= FRAME_TIME {
+                time_acc -= FRAME_TIME;
+                let keys: Vec<Keycode> = device_state.get_keys();
+                if keys.contains(&Keycode::Escape) {
+                    if let Err(e) = self.flush_save() {
+                        error!("writing save file failed. {}", e);
+                    }
+                    control_flow.set_exit();
+                    return;
+                }
+                for (key, button) in KEYMAP.iter() {
+                    self.mem.bus.joypad1.set_button_pressed_status(*button, keys.contains(key));
+                }
+                self.run_frame();
+                if self.save.as_ref().is_some_and(|save| save.due()) {
+                    if let Err(e) = self.flush_save() {
+                        error!("writing save file failed. {}", e);
+                    }
+                }
+                pixels.frame_mut().copy_from_slice(&self.mem.bus.ppu.frame.data);
+                pixels.render().expect("rendering is failed");
+            } else {
+                sleep(Duration::from_millis(1));
+            }
+            match event {
+                Event::WindowEvent {
+                    event: WindowEvent::CloseRequested,
+                    ..
+                } => {
+                    if let Err(e) = self.flush_save() {
+                        error!("writing save file failed. {}", e);
+                    }
+                    control_flow.set_exit();
+                }
+                _ => (),
+            }
+        });
     }
 }
@@ -0,0 +1,24 @@
+// helpers shared by the unit tests
+use crate::emu::Emu;
+
+// put `code` at $0600 and run `steps` instructions from there.
+// returns the cpu cycles they took.
+pub fn run(emu: &mut Emu, code: &[u8], steps: usize) -> u32 {
+    emu.load_easy(code.to_vec());
+    let start = emu.stat.cycle_counter;
+    for _ in 0..steps {
+        emu.run_cpu_once();
+    }
+    emu.stat.cycle_counter - start
+}
+
+// a header of `size` zero bytes with the (offset, bytes) `fields` written over
+// it, followed by `body`. builds ines files, nsf files and nsfe chunks.
+pub fn image(size: usize, fields: &[(usize, &[u8])], body: &[u8]) -> Vec<u8> {
+    let mut raw = vec![0; size];
+    for &(offset, bytes) in fields {
+        raw[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+    raw.extend_from_slice(body);
+    raw
+}
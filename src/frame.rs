@@ -0,0 +1,37 @@
+use std::io::Write;
+
+// 256x240 RGBA picture produced by the ppu
+pub struct Frame {
+    pub data: Vec<u8>,
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Frame::new()
+    }
+}
+
+impl Frame {
+    pub const WIDTH: usize = 256;
+    pub const HEIGHT: usize = 240;
+
+    pub fn new() -> Self {
+        Frame {
+            data: vec![0; Frame::WIDTH * Frame::HEIGHT * 4],
+        }
+    }
+
+    #[inline]
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgb: (u8, u8, u8)) {
+        let base = (y * Frame::WIDTH + x) * 4;
+        self.data[base..base + 4].copy_from_slice(&[rgb.0, rgb.1, rgb.2, 0xff]);
+    }
+
+    // dump as a binary ppm, readable by most image viewers
+    pub fn write_ppm(&self, path: &str) -> std::io::Result<()> {
+        let mut file = std::fs::File::create(path)?;
+        write!(file, "P6\n{} {}\n255\n", Frame::WIDTH, Frame::HEIGHT)?;
+        let rgb: Vec<u8> = self.data.chunks(4).flat_map(|p| p[0..3].to_vec()).collect();
+        file.write_all(&rgb)
+    }
+}
@@ -0,0 +1,150 @@
+use crate::emu::Emu;
+use crate::joypad::JoypadButton;
+use std::io::{self, Write};
+
+// controller 1 input for headless runs. each line is `<frame> <buttons>`, where
+// buttons is a comma separated list of a, b, select, start, up, down, left, right
+// (or `-` for none). the buttons stay held from that frame until the next line.
+// empty lines and lines starting with # are skipped.
+//
+//   0 -
+//   120 start
+//   130 right,a
+pub struct InputScript {
+    events: Vec<(u32, JoypadButton)>,
+}
+
+impl InputScript {
+    pub fn empty() -> Self {
+        InputScript { events: vec![] }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut events = vec![];
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let frame = fields.next()
+                .and_then(|f| f.parse::<u32>().ok())
+                .ok_or(format!("line {}: expected a frame number", n + 1))?;
+            let mut buttons = JoypadButton::empty();
+            for name in fields.next().unwrap_or("-").split(',') {
+                buttons |= match name.to_ascii_lowercase().as_str() {
+                    "-" => JoypadButton::empty(),
+                    "a" => JoypadButton::BUTTON_A,
+                    "b" => JoypadButton::BUTTON_B,
+                    "select" => JoypadButton::SELECT,
+                    "start" => JoypadButton::START,
+                    "up" => JoypadButton::UP,
+                    "down" => JoypadButton::DOWN,
+                    "left" => JoypadButton::LEFT,
+                    "right" => JoypadButton::RIGHT,
+                    other => return Err(format!("line {}: unknown button {}", n + 1, other)),
+                };
+            }
+            events.push((frame, buttons));
+        }
+        events.sort_by_key(|&(frame, _)| frame);
+        Ok(InputScript { events: events })
+    }
+
+    pub fn buttons_at(&self, frame: u32) -> JoypadButton {
+        self.events.iter()
+            .take_while(|&&(start, _)| start <= frame)
+            .last()
+            .map_or(JoypadButton::empty(), |&(_, buttons)| buttons)
+    }
+}
+
+// 16 bit mono pcm .wav
+pub fn write_wav<W: Write>(out: &mut W, sample_rate: u32, samples: &[i16]) -> io::Result<()> {
+    let data_len = samples.len() as u32 * 2;
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_len).to_le_bytes())?;
+    out.write_all(b"WAVE")?;
+    out.write_all(b"fmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    // pcm, 1 channel
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&sample_rate.to_le_bytes())?;
+    out.write_all(&(sample_rate * 2).to_le_bytes())?;
+    // block align, bits per sample
+    out.write_all(&2u16.to_le_bytes())?;
+    out.write_all(&16u16.to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&data_len.to_le_bytes())?;
+    for s in samples {
+        out.write_all(&s.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+impl Emu {
+    // run `frames` frames without a window, feeding controller 1 from `script`,
+    // and return the audio at the mixer's sample rate
+    pub fn record_audio(&mut self, frames: u32, script: &InputScript) -> Vec<i16> {
+        let mut samples: Vec<i16> = vec![];
+        for frame in 0..frames {
+            self.mem.bus.joypad1.status = script.buttons_at(frame);
+            self.run_frame();
+            self.drain_samples(&mut samples);
+        }
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_script() {
+        let script = InputScript::parse("# intro\n\n130 right,A\n0 -\n120   start\n").unwrap();
+        assert_eq!(script.buttons_at(0), JoypadButton::empty());
+        assert_eq!(script.buttons_at(119), JoypadButton::empty());
+        assert_eq!(script.buttons_at(120), JoypadButton::START);
+        assert_eq!(script.buttons_at(129), JoypadButton::START);
+        assert_eq!(script.buttons_at(130), JoypadButton::RIGHT | JoypadButton::BUTTON_A);
+        assert_eq!(script.buttons_at(10_000), JoypadButton::RIGHT | JoypadButton::BUTTON_A);
+    }
+
+    #[test]
+    fn buttons_default_to_none() {
+        assert_eq!(InputScript::empty().buttons_at(5), JoypadButton::empty());
+        let script = InputScript::parse("10\n20 up,down,left,b,select").unwrap();
+        assert_eq!(script.buttons_at(9), JoypadButton::empty());
+        assert_eq!(script.buttons_at(10), JoypadButton::empty());
+        assert_eq!(script.buttons_at(20), JoypadButton::UP | JoypadButton::DOWN | JoypadButton::LEFT
+                   | JoypadButton::BUTTON_B | JoypadButton::SELECT);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(InputScript::parse("0 -\nstart").err(), Some("line 2: expected a frame number".to_string()));
+        assert_eq!(InputScript::parse("5 a,jump").err(), Some("line 1: unknown button jump".to_string()));
+    }
+
+    #[test]
+    fn wav_header() {
+        let mut out = vec![];
+        write_wav(&mut out, 44100, &[1, -2]).unwrap();
+        assert_eq!(out.len(), 44 + 4);
+        assert_eq!(&out[0..4], b"RIFF");
+        assert_eq!(out[4..8], 40u32.to_le_bytes());
+        assert_eq!(&out[8..16], b"WAVEfmt ");
+        assert_eq!(out[16..20], 16u32.to_le_bytes());
+        // pcm, mono
+        assert_eq!(out[20..24], [1, 0, 1, 0]);
+        assert_eq!(out[24..28], 44100u32.to_le_bytes());
+        assert_eq!(out[28..32], 88200u32.to_le_bytes());
+        // block align 2, 16 bits
+        assert_eq!(out[32..36], [2, 0, 16, 0]);
+        assert_eq!(&out[36..40], b"data");
+        assert_eq!(out[40..44], 4u32.to_le_bytes());
+        assert_eq!(out[44..], [0x01, 0x00, 0xfe, 0xff]);
+    }
+}
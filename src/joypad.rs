@@ -0,0 +1,53 @@
+use bitflags::bitflags;
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Default)]
+    pub struct JoypadButton: u8 {
+        const RIGHT    = 0b10000000;
+        const LEFT     = 0b01000000;
+        const DOWN     = 0b00100000;
+        const UP       = 0b00010000;
+        const START    = 0b00001000;
+        const SELECT   = 0b00000100;
+        const BUTTON_B = 0b00000010;
+        const BUTTON_A = 0b00000001;
+    }
+}
+
+// standard controller. $4016 bit 0 is the strobe, and each read shifts out
+// one button in the order A, B, Select, Start, Up, Down, Left, Right.
+#[derive(Default)]
+pub struct Joypad {
+    strobe: bool,
+    index: u8,
+    pub status: JoypadButton,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Joypad::default()
+    }
+
+    pub fn write(&mut self, data: u8) {
+        self.strobe = data & 1 == 1;
+        if self.strobe {
+            self.index = 0;
+        }
+    }
+
+    pub fn read(&mut self) -> u8 {
+        // after all 8 buttons an official controller keeps returning 1
+        if self.index > 7 {
+            return 1;
+        }
+        let r = (self.status.bits() >> self.index) & 1;
+        if !self.strobe {
+            self.index += 1;
+        }
+        r
+    }
+
+    pub fn set_button_pressed_status(&mut self, button: JoypadButton, pressed: bool) {
+        self.status.set(button, pressed);
+    }
+}
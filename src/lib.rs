@@ -3,7 +3,19 @@ pub mod easy6502;
 pub mod emu;
 pub mod mem;
 pub mod ops;
+pub mod unofficial_ops;
 pub mod ppu;
+pub mod frame;
+pub mod palette;
 pub mod cart;
+pub mod mapper;
 pub mod bus;
+pub mod joypad;
+pub mod apu;
+pub mod mixer;
+pub mod headless;
+pub mod nsf;
+pub mod save;
 
+#[cfg(test)]
+mod fixture;
@@ -1,14 +1,37 @@
 use clap::Parser;
 use flexi_logger::{FileSpec, Logger, WriteMode};
 use nesnes::emu::Emu;
+use nesnes::headless::{write_wav, InputScript};
+use nesnes::nsf::Nsf;
 use std::fs::File;
-use std::io::BufReader;
-use std::io::Read;
 use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[derive(Parser)]
 struct Cli {
     rompath: Option<String>,
+    /// battery save file, <ROMFILE>.sav by default
+    #[arg(long)]
+    save: Option<PathBuf>,
+    /// seconds between save file flushes
+    #[arg(long, default_value_t = 10)]
+    save_interval: u64,
+    /// run without a window and write the audio to this .wav file
+    #[arg(long)]
+    wav: Option<PathBuf>,
+    /// frames to run with --wav (play calls for nsf files)
+    #[arg(long, default_value_t = 600)]
+    frames: u32,
+    /// nsf track to render, 1 based. defaults to the file's starting song
+    #[arg(long)]
+    track: Option<u8>,
+    /// controller input script for --wav, lines of `<frame> <buttons>`
+    #[arg(long)]
+    input: Option<PathBuf>,
+    /// sample rate of the --wav output
+    #[arg(long, default_value_t = 44100)]
+    sample_rate: u32,
 }
 
 fn main() {
@@ -17,18 +40,75 @@ fn main() {
         .log_to_file(FileSpec::default().suppress_timestamp())
         .write_mode(WriteMode::BufferAndFlush)
         .start().unwrap();
-    /* 
-    if let Some(path) = Cli::parse().rompath {
-        let file = File::open(path).unwrap();
-        let mut buf_reader = BufReader::new(file);
-        let mut contents = Vec::new();
-        buf_reader.read_to_end(&mut contents).unwrap();
+    let cli = Cli::parse();
+    if let Some(path) = cli.rompath {
+        let contents = std::fs::read(&path).unwrap_or_else(|e| {
+            eprintln!("failed to read {}: {}", path, e);
+            std::process::exit(1);
+        });
+        let mut emu = Emu::default();
+        if Nsf::is_nsf(&contents) {
+            let nsf = Nsf::from_raw(&contents).unwrap_or_else(|e| {
+                eprintln!("failed to load nsf: {}", e);
+                std::process::exit(1);
+            });
+            let Some(wav) = cli.wav else {
+                eprintln!("nsf files can only be rendered with --wav");
+                std::process::exit(1);
+            };
+            if let Err(e) = emu.load_nsf(&nsf) {
+                eprintln!("failed to load nsf: {}", e);
+                std::process::exit(1);
+            }
+            let track = cli.track.unwrap_or(nsf.start_song).clamp(1, nsf.songs.max(1));
+            emu.set_sample_rate(cli.sample_rate);
+            let samples = emu.record_nsf(&nsf, track - 1, cli.frames);
+            save_wav(&wav, cli.sample_rate, &samples);
+            return;
+        }
+        if let Err(e) = emu.load(contents) {
+            eprintln!("failed to load rom: {}", e);
+            std::process::exit(1);
+        }
+        if let Some(wav) = cli.wav {
+            let script = match cli.input {
+                Some(input) => {
+                    let text = std::fs::read_to_string(&input).unwrap_or_else(|e| {
+                        eprintln!("failed to read {}: {}", input.display(), e);
+                        std::process::exit(1);
+                    });
+                    InputScript::parse(&text).unwrap_or_else(|e| {
+                        eprintln!("bad input script: {}", e);
+                        std::process::exit(1);
+                    })
+                }
+                None => InputScript::empty(),
+            };
+            emu.set_sample_rate(cli.sample_rate);
+            let samples = emu.record_audio(cli.frames, &script);
+            save_wav(&wav, cli.sample_rate, &samples);
+            return;
+        }
+        let save_path = cli.save.unwrap_or_else(|| Path::new(&path).with_extension("sav"));
+        if let Err(e) = emu.attach_save(save_path, Duration::from_secs(cli.save_interval)) {
+            eprintln!("failed to read save file: {}", e);
+        }
+        emu.run();
     } else {
         println!("usage: nesnes [ROMFILE]");
     }
-    */
+}
 
-    cpu_test();
+fn save_wav(path: &Path, sample_rate: u32, samples: &[i16]) {
+    let result = File::create(path).and_then(|file| {
+        let mut out = std::io::BufWriter::new(file);
+        write_wav(&mut out, sample_rate, samples)?;
+        out.flush()
+    });
+    if let Err(e) = result {
+        eprintln!("failed to write {}: {}", path.display(), e);
+        std::process::exit(1);
+    }
 }
 
 
@@ -64,13 +144,13 @@ fn easy6502_test() {
 fn cpu_test() {
     let cart = include_bytes!("../nestest.nes");
     let mut emu = Emu::default();
-    emu.load(cart.to_vec());
+    emu.load(cart.to_vec()).unwrap();
     emu.cpu.pc = 0xc000;
     emu.run_cpu_with_callback(8192, |e| {
         Emu::cpudebug_for_test(e);
         if e.cpu.pc == 0xdbb5 {
             use pretty_hex::*;                    let mut file = std::fs::File::create("dbb5mem.txt").unwrap();
-                    file.write(format!("{:?}", e.mem.mem[0x000..02000].hex_dump()).to_string().as_bytes()).expect("FUCKWRITER");
+                    file.write(format!("{:?}", e.mem.bus.ram[0x000..0x800].hex_dump()).to_string().as_bytes()).expect("FUCKWRITER");
         }
 });
 }
\ No newline at end of file
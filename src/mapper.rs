@@ -0,0 +1,174 @@
+use crate::cart::{Mirroring, Rom, RomError};
+use log::debug;
+
+mod nrom;
+mod mmc1;
+mod uxrom;
+mod cnrom;
+mod axrom;
+mod mmc3;
+mod nsf;
+
+pub use nrom::Nrom;
+pub use mmc1::Mmc1;
+pub use uxrom::Uxrom;
+pub use cnrom::Cnrom;
+pub use axrom::Axrom;
+pub use mmc3::Mmc3;
+pub use nsf::NsfMapper;
+
+// cartridge board. it sits on both the cpu bus ($4020-$ffff) and the ppu bus
+// ($0000-$1fff) and decides where each access lands, so bank switching,
+// mirroring control and board irqs all live behind this trait.
+pub trait Mapper {
+    // None leaves the data bus floating (open bus)
+    fn cpu_read(&self, addr: u16) -> Option<u8>;
+    fn cpu_write(&mut self, addr: u16, data: u8);
+    fn ppu_read(&mut self, addr: u16) -> u8;
+    fn ppu_write(&mut self, addr: u16, data: u8);
+    // current nametable layout
+    fn mirroring(&self) -> Mirroring;
+    // state of the board's /IRQ output
+    fn irq(&self) -> bool {
+        false
+    }
+    // called once per cpu cycle
+    fn cpu_clock(&mut self) {}
+    // every address the ppu puts on its bus, including nametable and palette
+    // accesses that never reach the cartridge
+    fn ppu_addr(&mut self, _addr: u16) {}
+    // prg ram at $6000-$7fff, if the board has any
+    fn prg_ram(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+    // prg ram is kept by a battery and should be saved
+    fn battery(&self) -> bool {
+        false
+    }
+}
+
+// pattern table memory on the board: chr rom, chr ram, or both. the ram comes
+// after the rom, so banks past the end of the rom select ram. only ram is writable.
+pub struct ChrMemory {
+    data: Vec<u8>,
+    rom_len: usize,
+}
+
+impl ChrMemory {
+    // the ram size comes from the header. ines 1.0 has no field for it, so a board
+    // without chr rom gets the usual 8K.
+    pub fn from_rom(rom: &Rom) -> Self {
+        let mut ram_size = rom.chr_ram_size;
+        if !rom.nes2 && rom.chr_rom.is_empty() && ram_size == 0 {
+            ram_size = 0x2000;
+        }
+        let mut data = rom.chr_rom.clone();
+        data.resize(rom.chr_rom.len() + ram_size, 0);
+        ChrMemory {
+            data: data,
+            rom_len: rom.chr_rom.len(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    // a board without any chr memory reads 0
+    #[inline]
+    pub fn read(&self, offset: usize) -> u8 {
+        if self.data.is_empty() {
+            return 0;
+        }
+        self.data[offset % self.data.len()]
+    }
+
+    #[inline]
+    pub fn write(&mut self, offset: usize, data: u8) {
+        if self.data.is_empty() {
+            return;
+        }
+        let i = offset % self.data.len();
+        if i >= self.rom_len {
+            self.data[i] = data;
+        } else {
+            debug!("attempt to write to chr rom space {:04x}", offset);
+        }
+    }
+}
+
+pub fn from_rom(rom: Rom) -> Result<Box<dyn Mapper>, RomError> {
+    // only prg ram is saved
+    if rom.chr_nvram_size > 0 {
+        return Err(RomError::UNSUPPORTED_FORMAT("battery backed chr ram is not supported"));
+    }
+    match rom.mapper {
+        0 => Ok(Box::new(Nrom::new(rom))),
+        1 => Ok(Box::new(Mmc1::new(rom))),
+        2 => Ok(Box::new(Uxrom::new(rom))),
+        3 => Ok(Box::new(Cnrom::new(rom))),
+        4 => Ok(Box::new(Mmc3::new(rom))),
+        7 => Ok(Box::new(Axrom::new(rom))),
+        n => Err(RomError::UNSUPPORTED_MAPPER(n)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chr_ram_after_chr_rom() {
+        let mut rom = Rom::nothing();
+        rom.nes2 = true;
+        rom.chr_rom = vec![0x11; 0x2000];
+        rom.chr_ram_size = 0x1000;
+        let mut chr = ChrMemory::from_rom(&rom);
+        assert_eq!(chr.len(), 0x3000);
+        chr.write(0x0010, 0x22);
+        chr.write(0x2010, 0x33);
+        assert_eq!((chr.read(0x0010), chr.read(0x2010)), (0x11, 0x33));
+    }
+
+    #[test]
+    fn chr_ram_size() {
+        // nes 2.0 sizes are taken as they are
+        let mut rom = Rom::nothing();
+        rom.nes2 = true;
+        rom.chr_ram_size = 0x800;
+        assert_eq!(ChrMemory::from_rom(&rom).len(), 0x800);
+        rom.chr_ram_size = 0;
+        let mut chr = ChrMemory::from_rom(&rom);
+        chr.write(0, 1);
+        assert_eq!((chr.len(), chr.read(0)), (0, 0));
+        // ines 1.0 boards without chr rom get 8K
+        assert_eq!(ChrMemory::from_rom(&Rom::nothing()).len(), 0x2000);
+    }
+
+    #[test]
+    fn nrom_prg_ram() {
+        // ines 1.0 always declares 8K, so blargg style roms can report at $6000
+        let raw = crate::fixture::image(16, &[(0, b"NES\x1a"), (4, &[1, 1])], &[0; 0x6000]);
+        let mut cart = from_rom(Rom::from_raw(&raw).unwrap()).unwrap();
+        cart.cpu_write(0x6000, 0x80);
+        assert_eq!(cart.cpu_read(0x6000), Some(0x80));
+        // a nes 2.0 header without any prg ram leaves $6000 open
+        let mut rom = Rom::nothing();
+        rom.nes2 = true;
+        rom.prg_rom = vec![0; 0x4000];
+        let cart = from_rom(rom).unwrap();
+        assert_eq!(cart.cpu_read(0x6000), None);
+    }
+
+    #[test]
+    fn chr_nvram_is_rejected() {
+        let mut rom = Rom::nothing();
+        rom.prg_rom = vec![0; 0x4000];
+        rom.chr_nvram_size = 0x2000;
+        assert_eq!(from_rom(rom).err(), Some(RomError::UNSUPPORTED_FORMAT("battery backed chr ram is not supported")));
+    }
+}
@@ -0,0 +1,58 @@
+use crate::cart::{Mirroring, Rom};
+use crate::mapper::{ChrMemory, Mapper};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+
+// mapper 7. switchable 32K prg, 8K chr, and single-screen mirroring picked
+// by bit 4 of the bank register. only the AMROM variant has bus conflicts and
+// games written for ANROM/AOROM rely on not having them, so they are not emulated.
+pub struct Axrom {
+    prg_rom: Vec<u8>,
+    chr: ChrMemory,
+    bank: u8,
+}
+
+impl Axrom {
+    pub fn new(rom: Rom) -> Self {
+        Axrom {
+            chr: ChrMemory::from_rom(&rom),
+            prg_rom: rom.prg_rom,
+            bank: 0,
+        }
+    }
+}
+
+impl Mapper for Axrom {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x8000..=0xffff => {
+                let banks = (self.prg_rom.len() / PRG_BANK_SIZE).max(1);
+                let bank = (self.bank & 0b111) as usize % banks;
+                self.prg_rom.get(bank * PRG_BANK_SIZE + (addr - 0x8000) as usize).copied()
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if addr >= 0x8000 {
+            self.bank = data;
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(addr as usize)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr.write(addr as usize, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.bank & 0b1_0000 == 0 {
+            Mirroring::SINGLE_SCREEN_LOWER
+        } else {
+            Mirroring::SINGLE_SCREEN_UPPER
+        }
+    }
+}
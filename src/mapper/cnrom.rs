@@ -0,0 +1,64 @@
+use crate::cart::{Mirroring, Rom};
+use crate::mapper::{ChrMemory, Mapper};
+
+const CHR_BANK_SIZE: usize = 0x2000;
+
+// mapper 3. fixed 16K or 32K prg like nrom, switchable 8K chr.
+// writes have bus conflicts with the prg rom.
+pub struct Cnrom {
+    prg_rom: Vec<u8>,
+    chr: ChrMemory,
+    mirroring: Mirroring,
+    chr_bank: u8,
+}
+
+impl Cnrom {
+    pub fn new(rom: Rom) -> Self {
+        Cnrom {
+            chr: ChrMemory::from_rom(&rom),
+            prg_rom: rom.prg_rom,
+            mirroring: rom.screen_mirroring,
+            chr_bank: 0,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let banks = (self.chr.len() / CHR_BANK_SIZE).max(1);
+        (self.chr_bank as usize % banks) * CHR_BANK_SIZE + addr as usize
+    }
+}
+
+impl Mapper for Cnrom {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x8000..=0xffff => {
+                let mut addr = addr - 0x8000;
+                if self.prg_rom.len() == 0x4000 {
+                    addr &= 0x3fff;
+                }
+                self.prg_rom.get(addr as usize).copied()
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if addr >= 0x8000 {
+            let rom = self.cpu_read(addr).unwrap_or(0xff);
+            self.chr_bank = data & rom;
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(self.chr_offset(addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        let offset = self.chr_offset(addr);
+        self.chr.write(offset, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
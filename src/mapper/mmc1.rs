@@ -0,0 +1,169 @@
+use crate::cart::{Mirroring, Rom};
+use crate::mapper::{ChrMemory, Mapper};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x1000;
+const PRG_RAM_SIZE: usize = 0x2000;
+
+// mapper 1 (SxROM). registers are loaded serially through a 5 bit shift register:
+// each write to $8000-$ffff shifts in bit 0, and the fifth write copies the value
+// into the register selected by address bits 13-14. a write with bit 7 set resets it.
+pub struct Mmc1 {
+    prg_rom: Vec<u8>,
+    chr: ChrMemory,
+    prg_ram: [u8; PRG_RAM_SIZE],
+    battery: bool,
+    shift: u8,
+    shift_count: u8,
+    // $8000 CPPMM: chr mode, prg mode, mirroring
+    control: u8,
+    chr_bank0: u8,
+    chr_bank1: u8,
+    // $e000 RPPPP: prg-ram disable, prg bank
+    prg_bank: u8,
+    // cpu cycle counter, to drop the second write of read-modify-write instructions
+    cycles: u64,
+    last_write: u64,
+}
+
+impl Mmc1 {
+    pub fn new(rom: Rom) -> Self {
+        Mmc1 {
+            chr: ChrMemory::from_rom(&rom),
+            prg_rom: rom.prg_rom,
+            prg_ram: [0; PRG_RAM_SIZE],
+            battery: rom.battery,
+            shift: 0,
+            shift_count: 0,
+            // power on in prg mode 3, last bank fixed at $c000
+            control: 0x0c,
+            chr_bank0: 0,
+            chr_bank1: 0,
+            prg_bank: 0,
+            cycles: 0,
+            last_write: u64::MAX,
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x8000..=0x9fff => self.control = data,
+            0xa000..=0xbfff => self.chr_bank0 = data,
+            0xc000..=0xdfff => self.chr_bank1 = data,
+            _ => self.prg_bank = data,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank & 0b1_0000 == 0
+    }
+
+    // 512K boards (SUROM) pick the 256K half with bit 4 of the chr bank register
+    fn prg_outer(&self) -> usize {
+        if self.prg_rom.len() > 0x40000 {
+            self.chr_bank0 as usize & 0b1_0000
+        } else {
+            0
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let banks = (self.prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let outer = self.prg_outer();
+        let bank = (self.prg_bank & 0x0f) as usize;
+        let high = addr >= 0xc000;
+        let bank = match (self.control >> 2) & 0b11 {
+            // 32K mode ignores the low bit
+            0 | 1 => (bank & !1) | high as usize,
+            2 => if high { bank } else { 0 },
+            _ => if high { 0x0f } else { bank },
+        };
+        ((outer | bank) % banks) * PRG_BANK_SIZE + (addr as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let banks = (self.chr.len() / CHR_BANK_SIZE).max(1);
+        let high = addr >= 0x1000;
+        let bank = if self.control & 0b1_0000 == 0 {
+            // 8K mode ignores the low bit
+            (self.chr_bank0 as usize & !1) | high as usize
+        } else if high {
+            self.chr_bank1 as usize
+        } else {
+            self.chr_bank0 as usize
+        };
+        (bank % banks) * CHR_BANK_SIZE + (addr as usize & (CHR_BANK_SIZE - 1))
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7fff if self.prg_ram_enabled() => {
+                Some(self.prg_ram[(addr - 0x6000) as usize])
+            }
+            0x8000..=0xffff => self.prg_rom.get(self.prg_offset(addr)).copied(),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7fff if self.prg_ram_enabled() => {
+                self.prg_ram[(addr - 0x6000) as usize] = data;
+            }
+            0x8000..=0xffff => {
+                // the board ignores a write on the cycle right after another one
+                let consecutive = self.cycles == self.last_write.wrapping_add(1);
+                self.last_write = self.cycles;
+                if consecutive {
+                    return;
+                }
+                if data & 0x80 != 0 {
+                    self.shift = 0;
+                    self.shift_count = 0;
+                    self.control |= 0x0c;
+                    return;
+                }
+                self.shift |= (data & 1) << self.shift_count;
+                self.shift_count += 1;
+                if self.shift_count == 5 {
+                    self.write_register(addr, self.shift);
+                    self.shift = 0;
+                    self.shift_count = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(self.chr_offset(addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        let offset = self.chr_offset(addr);
+        self.chr.write(offset, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0b11 {
+            0 => Mirroring::SINGLE_SCREEN_LOWER,
+            1 => Mirroring::SINGLE_SCREEN_UPPER,
+            2 => Mirroring::VERTICAL,
+            _ => Mirroring::HORIZONTAL,
+        }
+    }
+
+    fn cpu_clock(&mut self) {
+        self.cycles += 1;
+    }
+
+    fn prg_ram(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn battery(&self) -> bool {
+        self.battery
+    }
+}
@@ -0,0 +1,181 @@
+use crate::cart::{Mirroring, Rom};
+use crate::mapper::{ChrMemory, Mapper};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+const PRG_RAM_SIZE: usize = 0x2000;
+// cpu cycles a12 has to stay low before a rising edge clocks the counter.
+// this filters out the short low periods between sprite pattern fetches.
+const A12_LOW_CYCLES: u64 = 3;
+
+// mapper 4 (TxROM). four 8K prg windows, two 2K and four 1K chr windows, and a
+// scanline counter clocked by rising edges of ppu address line 12.
+pub struct Mmc3 {
+    prg_rom: Vec<u8>,
+    chr: ChrMemory,
+    prg_ram: [u8; PRG_RAM_SIZE],
+    battery: bool,
+    four_screen: bool,
+    mirroring: Mirroring,
+    // $8000: bank register select, prg mode (bit 6), chr a12 inversion (bit 7)
+    bank_select: u8,
+    // R0-R7
+    banks: [u8; 8],
+    prg_ram_enabled: bool,
+    prg_ram_protected: bool,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    a12: bool,
+    a12_low_since: u64,
+    cycles: u64,
+}
+
+impl Mmc3 {
+    pub fn new(rom: Rom) -> Self {
+        Mmc3 {
+            chr: ChrMemory::from_rom(&rom),
+            prg_rom: rom.prg_rom,
+            prg_ram: [0; PRG_RAM_SIZE],
+            battery: rom.battery,
+            four_screen: rom.screen_mirroring == Mirroring::FOUR_SCREEN,
+            mirroring: rom.screen_mirroring,
+            bank_select: 0,
+            banks: [0, 2, 4, 5, 6, 7, 0, 1],
+            prg_ram_enabled: true,
+            prg_ram_protected: false,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            a12: false,
+            a12_low_since: 0,
+            cycles: 0,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let banks = (self.prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let second_last = banks.saturating_sub(2);
+        let swap = self.bank_select & 0b0100_0000 != 0;
+        let bank = match ((addr - 0x8000) / 0x2000, swap) {
+            (0, false) | (2, true) => self.banks[6] as usize,
+            (0, true) | (2, false) => second_last,
+            (1, _) => self.banks[7] as usize,
+            _ => banks - 1,
+        };
+        (bank % banks) * PRG_BANK_SIZE + (addr as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let banks = (self.chr.len() / CHR_BANK_SIZE).max(1);
+        // inversion swaps the 2K and the 1K halves
+        let addr = if self.bank_select & 0b1000_0000 != 0 { addr ^ 0x1000 } else { addr };
+        let bank = match addr / 0x400 {
+            0 => self.banks[0] & !1,
+            1 => self.banks[0] | 1,
+            2 => self.banks[1] & !1,
+            3 => self.banks[1] | 1,
+            n => self.banks[n as usize - 2],
+        } as usize;
+        (bank % banks) * CHR_BANK_SIZE + (addr as usize & (CHR_BANK_SIZE - 1))
+    }
+
+    fn clock_counter(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7fff if self.prg_ram_enabled => {
+                Some(self.prg_ram[(addr - 0x6000) as usize])
+            }
+            0x8000..=0xffff => self.prg_rom.get(self.prg_offset(addr)).copied(),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        let even = addr & 1 == 0;
+        match addr {
+            0x6000..=0x7fff if self.prg_ram_enabled && !self.prg_ram_protected => {
+                self.prg_ram[(addr - 0x6000) as usize] = data;
+            }
+            0x8000..=0x9fff if even => self.bank_select = data,
+            0x8000..=0x9fff => self.banks[(self.bank_select & 0b111) as usize] = data,
+            0xa000..=0xbfff if even && !self.four_screen => {
+                self.mirroring = if data & 1 == 0 { Mirroring::VERTICAL } else { Mirroring::HORIZONTAL };
+            }
+            // four-screen boards ignore the mirroring register
+            0xa000..=0xbfff if even => {}
+            0xa000..=0xbfff => {
+                self.prg_ram_enabled = data & 0b1000_0000 != 0;
+                self.prg_ram_protected = data & 0b0100_0000 != 0;
+            }
+            0xc000..=0xdfff if even => self.irq_latch = data,
+            0xc000..=0xdfff => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            0xe000..=0xffff if even => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            0xe000..=0xffff => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(self.chr_offset(addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        let offset = self.chr_offset(addr);
+        self.chr.write(offset, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn cpu_clock(&mut self) {
+        self.cycles += 1;
+    }
+
+    fn prg_ram(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn battery(&self) -> bool {
+        self.battery
+    }
+
+    fn ppu_addr(&mut self, addr: u16) {
+        let a12 = addr & 0x1000 != 0;
+        if a12 && !self.a12 && self.cycles - self.a12_low_since >= A12_LOW_CYCLES {
+            self.clock_counter();
+        }
+        if !a12 && self.a12 {
+            self.a12_low_since = self.cycles;
+        }
+        self.a12 = a12;
+    }
+}
@@ -0,0 +1,80 @@
+use crate::cart::{Mirroring, Rom};
+use crate::mapper::{ChrMemory, Mapper};
+use log::debug;
+
+const PRG_RAM_SIZE: usize = 0x2000;
+
+// mapper 0. 16K or 32K prg, 8K chr, no bank switching.
+// 16K boards mirror $8000-$bfff at $c000-$ffff.
+// prg ram at $6000 is there unless a nes 2.0 header says the board has none.
+// ines 1.0 headers always get 8K, like the other boards.
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+    chr: ChrMemory,
+    prg_ram: Vec<u8>,
+    battery: bool,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(rom: Rom) -> Self {
+        let has_ram = rom.battery || rom.trainer.is_some()
+            || rom.prg_ram_size + rom.prg_nvram_size > 0;
+        Nrom {
+            chr: ChrMemory::from_rom(&rom),
+            prg_rom: rom.prg_rom,
+            prg_ram: if has_ram { vec![0; PRG_RAM_SIZE] } else { vec![] },
+            battery: rom.battery,
+            mirroring: rom.screen_mirroring,
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x8000..=0xffff => {
+                let mut addr = addr - 0x8000;
+                if self.prg_rom.len() == 0x4000 {
+                    addr &= 0x3fff;
+                }
+                self.prg_rom.get(addr as usize).copied()
+            }
+            0x6000..=0x7fff => self.prg_ram.get((addr - 0x6000) as usize).copied(),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7fff if !self.prg_ram.is_empty() => {
+                self.prg_ram[(addr - 0x6000) as usize] = data;
+            }
+            _ => debug!("attempt to write to prg rom space {:04x} {:02x}", addr, data),
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(addr as usize)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr.write(addr as usize, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&mut self) -> Option<&mut [u8]> {
+        if self.prg_ram.is_empty() {
+            None
+        } else {
+            Some(&mut self.prg_ram)
+        }
+    }
+
+    fn battery(&self) -> bool {
+        self.battery
+    }
+}
@@ -0,0 +1,71 @@
+use crate::cart::{Mirroring, RomError};
+use crate::mapper::Mapper;
+use crate::nsf::Nsf;
+
+const PAGE_SIZE: usize = 0x1000;
+const PRG_RAM_SIZE: usize = 0x2000;
+
+// board for nsf music rips. $8000-$ffff is split into eight 4K windows selected
+// by $5ff8-$5fff, and there is 8K of ram at $6000-$7fff. rips without bank
+// switching are treated as the identity mapping of a flat 32K image.
+pub struct NsfMapper {
+    pages: Vec<u8>,
+    banks: [u8; 8],
+    prg_ram: [u8; PRG_RAM_SIZE],
+}
+
+impl NsfMapper {
+    pub fn new(nsf: &Nsf) -> Result<Self, RomError> {
+        let (padding, banks) = if nsf.bankswitched() {
+            ((nsf.load_addr & 0x0fff) as usize, nsf.banks)
+        } else if nsf.load_addr >= 0x8000 {
+            ((nsf.load_addr - 0x8000) as usize, [0, 1, 2, 3, 4, 5, 6, 7])
+        } else {
+            return Err(RomError::UNSUPPORTED_FORMAT("nsf load address below $8000"));
+        };
+        let len = (padding + nsf.data.len()).div_ceil(PAGE_SIZE).max(8) * PAGE_SIZE;
+        let mut pages = vec![0; len];
+        pages[padding..padding + nsf.data.len()].copy_from_slice(&nsf.data);
+        Ok(NsfMapper {
+            pages: pages,
+            banks: banks,
+            prg_ram: [0; PRG_RAM_SIZE],
+        })
+    }
+}
+
+impl Mapper for NsfMapper {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7fff => Some(self.prg_ram[(addr - 0x6000) as usize]),
+            0x8000..=0xffff => {
+                let window = ((addr - 0x8000) as usize) / PAGE_SIZE;
+                let page = self.banks[window] as usize % (self.pages.len() / PAGE_SIZE);
+                Some(self.pages[page * PAGE_SIZE + (addr as usize & (PAGE_SIZE - 1))])
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x5ff8..=0x5fff => self.banks[(addr - 0x5ff8) as usize] = data,
+            0x6000..=0x7fff => self.prg_ram[(addr - 0x6000) as usize] = data,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, _addr: u16) -> u8 {
+        0
+    }
+
+    fn ppu_write(&mut self, _addr: u16, _data: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::HORIZONTAL
+    }
+
+    fn prg_ram(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+}
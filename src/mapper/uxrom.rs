@@ -0,0 +1,59 @@
+use crate::cart::{Mirroring, Rom};
+use crate::mapper::{ChrMemory, Mapper};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+
+// mapper 2. switchable 16K prg at $8000, last bank fixed at $c000, 8K chr.
+// the bank register sits on top of the rom with no buffer, so the value
+// written is ANDed with the rom byte at the same address (bus conflict).
+pub struct Uxrom {
+    prg_rom: Vec<u8>,
+    chr: ChrMemory,
+    mirroring: Mirroring,
+    prg_bank: u8,
+}
+
+impl Uxrom {
+    pub fn new(rom: Rom) -> Self {
+        Uxrom {
+            chr: ChrMemory::from_rom(&rom),
+            prg_rom: rom.prg_rom,
+            mirroring: rom.screen_mirroring,
+            prg_bank: 0,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let banks = (self.prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let bank = if addr >= 0xc000 { banks - 1 } else { self.prg_bank as usize % banks };
+        bank * PRG_BANK_SIZE + (addr as usize & (PRG_BANK_SIZE - 1))
+    }
+}
+
+impl Mapper for Uxrom {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x8000..=0xffff => self.prg_rom.get(self.prg_offset(addr)).copied(),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if addr >= 0x8000 {
+            let rom = self.cpu_read(addr).unwrap_or(0xff);
+            self.prg_bank = data & rom;
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(addr as usize)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr.write(addr as usize, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
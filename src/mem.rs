@@ -1,106 +1,52 @@
-use crate::cart::Rom;
-
-const RAM_SIZE: usize = 0xffff;
-const CPU_RAM_ZERO: u16 = 0x0000;
-const CPU_RAM_ADDR_MASK: u16 = 0x07ff;
-const CPU_RAM_MIRROR_END: u16 = 0x1fff;
-const PPU_RAM_ZERO: u16 = 0x2000;
-const PPU_RAM_ADDR_MASK: u16 = 0x2007;
-const PPU_RAM_MIRROR_END: u16 = 0x3fff;
-const PRG_ROM_ZERO: u16 = 0x8000;
-const PRG_ROM_END: u16 = 0xffff;
+use crate::bus::Bus;
+use crate::mapper::Mapper;
 
+// cpu side view of the address space.
+// byte accesses go straight to the bus, which dispatches them to ram, ppu, io or the cartridge.
+#[derive(Default)]
 pub struct Mem {
-    pub mem: [u8; RAM_SIZE],
-    pub vram: [u8; 0x800],
-    pub oam: [u8; 0x100],
-    pub rom: Rom,
-}
-
-impl Default for Mem {
-    fn default() -> Self {
-        Mem {
-            mem: [0; RAM_SIZE],
-            rom: Rom::nothing(),
-            vram: [0; 0x800],
-            oam: [0; 0x100],
-        }
-    }
+    pub bus: Bus,
 }
 
 impl Mem {
-    pub fn set_rom(&mut self, rom: Rom) {
-        self.rom = rom;
+    pub fn new() -> Self {
+        Mem::default()
     }
-    #[inline]
-    pub fn translate_address_w(&mut self, addr: u16) -> &mut [u8] {
-        match addr {
-            CPU_RAM_ZERO..=CPU_RAM_MIRROR_END => {
-                &mut self.mem[addr as usize & CPU_RAM_ADDR_MASK as usize..]
-            }
-            PPU_RAM_ZERO..=PPU_RAM_MIRROR_END => {
-                &mut self.mem[addr as usize & PPU_RAM_ADDR_MASK as usize..]
-            }
-            PRG_ROM_ZERO..=PRG_ROM_END => {
-                let mut addr = addr - PRG_ROM_ZERO;
-                if self.rom.prg_rom.len() == 0x4000 && (addr & 0x4000 == 0x4000) {
-                    addr = addr ^ 0x4000;
-                }
-                &mut self.rom.prg_rom[addr as usize..]
-            }
-            _ => {
-                panic!("illegal address translation occured at: {:04x}", addr)
-            }
-        }
+    pub fn set_cart(&mut self, cart: Box<dyn Mapper>) {
+        self.bus.set_cart(cart);
     }
 
-    #[inline]
-    pub fn translate_address_r(&self, addr: u16) -> &[u8] {
-        match addr {
-            CPU_RAM_ZERO..=CPU_RAM_MIRROR_END => {
-                &self.mem[addr as usize & CPU_RAM_ADDR_MASK as usize..]
-            }
-            PPU_RAM_ZERO..=PPU_RAM_MIRROR_END => {
-                &self.mem[addr as usize & PPU_RAM_ADDR_MASK as usize..]
-            }
-            PRG_ROM_ZERO..=PRG_ROM_END => {
-                let mut addr = addr - PRG_ROM_ZERO;
-                if self.rom.prg_rom.len() == 0x4000 && (addr & 0x4000 == 0x4000) {
-                    addr = addr ^ 0x4000;
-                }
-                &self.rom.prg_rom[addr as usize..]
-            }
-            _ => {
-                panic!("illegal address translation occured at: {:04x}", addr)
-            }
-        }
-    }
     #[inline]
     pub fn copy_from_slice(&mut self, offset: usize, slice: &[u8]) {
-        self.mem[offset..offset + slice.len()].copy_from_slice(slice);
+        self.bus.ram[offset..offset + slice.len()].copy_from_slice(slice);
     }
 
     #[inline]
     pub fn store_u8(&mut self, addr: u16, data: u8) {
-        let p = Self::translate_address_w(self, addr);
-        p[0] = data;
+        self.bus.write(addr, data);
     }
     #[inline]
-    pub fn load_u8(&self, addr: u16) -> u8 {
-        let p = Self::translate_address_r(self, addr);
-        p[0]
+    pub fn load_u8(&mut self, addr: u16) -> u8 {
+        self.bus.read(addr)
+    }
+    #[inline]
+    pub fn peek_u8(&self, addr: u16) -> u8 {
+        self.bus.peek(addr)
     }
     #[inline]
     pub fn store_u16(&mut self, addr: u16, data: u16) {
-        let p = Self::translate_address_w(self, addr);
-        p[0..=1].copy_from_slice(&data.to_le_bytes());
+        let [l, h] = data.to_le_bytes();
+        self.store_u8(addr, l);
+        self.store_u8(addr.wrapping_add(1), h);
     }
     #[inline]
-    pub fn load_u16(&self, addr: u16) -> u16 {
-        let p = Self::translate_address_r(self, addr);
-        p[0] as u16 | ((p[1] as u16) << 8)
+    pub fn load_u16(&mut self, addr: u16) -> u16 {
+        let l = self.load_u8(addr) as u16;
+        let h = self.load_u8(addr.wrapping_add(1)) as u16;
+        l | (h << 8)
     }
-
+    #[inline]
+    pub fn peek_u16(&self, addr: u16) -> u16 {
+        self.peek_u8(addr) as u16 | ((self.peek_u8(addr.wrapping_add(1)) as u16) << 8)
     }
-
-
+}
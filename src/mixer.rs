@@ -0,0 +1,249 @@
+use log::debug;
+use std::f32::consts::PI;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+// band-limited steps: output samples a level change spreads over, and the
+// fractional sample positions the step shape is precomputed for. steps in
+// between are interpolated from the two nearest.
+const STEP_WIDTH: usize = 32;
+const STEP_PHASES: usize = 64;
+// step cutoff as a fraction of the sample rate. the window's transition band
+// is fully attenuated by nyquist.
+const STEP_CUTOFF: f64 = 0.4;
+
+// frontends receive the mixed output through this. implemented for Vec<f32>
+// and Vec<i16> so a buffer of either format can be filled directly.
+pub trait SampleSink {
+    fn append(&mut self, samples: &[f32]);
+}
+
+impl SampleSink for Vec<f32> {
+    fn append(&mut self, samples: &[f32]) {
+        self.extend_from_slice(samples);
+    }
+}
+
+impl SampleSink for Vec<i16> {
+    fn append(&mut self, samples: &[f32]) {
+        self.extend(samples.iter().map(|&s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16));
+    }
+}
+
+// first order filter. high-pass when `high` is set, low-pass otherwise.
+struct Filter {
+    high: bool,
+    k: f32,
+    prev_in: f32,
+    prev_out: f32,
+}
+
+impl Filter {
+    fn new(high: bool, cutoff: f32, sample_rate: u32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate as f32;
+        Filter {
+            high: high,
+            k: if high { rc / (rc + dt) } else { dt / (rc + dt) },
+            prev_in: 0.0,
+            prev_out: 0.0,
+        }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let y = if self.high {
+            self.k * (self.prev_out + x - self.prev_in)
+        } else {
+            self.prev_out + self.k * (x - self.prev_out)
+        };
+        self.prev_in = x;
+        self.prev_out = y;
+        y
+    }
+}
+
+// the difference between neighbouring output samples when the level steps up
+// by one at each of the STEP_PHASES + 1 positions from the start of a sample
+// to the start of the next one. a blackman
+// windowed sinc, so the step rises smoothly over STEP_WIDTH samples and has
+// nothing left above nyquist. every phase sums to one, a full step.
+fn step_table() -> Vec<[f32; STEP_WIDTH]> {
+    use std::f64::consts::PI;
+    let half = STEP_WIDTH as f64 / 2.0;
+    (0..=STEP_PHASES).map(|phase| {
+        let offset = phase as f64 / STEP_PHASES as f64;
+        let mut taps = [0.0; STEP_WIDTH];
+        for (i, tap) in taps.iter_mut().enumerate() {
+            // distance from the step to the middle of the span this tap covers
+            let x = i as f64 + 0.5 - half - offset;
+            let sinc = if x == 0.0 {
+                2.0 * STEP_CUTOFF
+            } else {
+                (2.0 * PI * STEP_CUTOFF * x).sin() / (PI * x)
+            };
+            let window = 0.42 + 0.5 * (PI * x / half).cos() + 0.08 * (2.0 * PI * x / half).cos();
+            *tap = sinc * window;
+        }
+        let sum: f64 = taps.iter().sum();
+        let mut step = [0.0; STEP_WIDTH];
+        for (s, t) in step.iter_mut().zip(taps.iter()) {
+            *s = (t / sum) as f32;
+        }
+        step
+    }).collect()
+}
+
+// combines the channel outputs the way the 2A03's resistor network does and
+// brings them from the cpu rate down to the host rate. each change of the
+// mixed level is added as a band-limited step, so tones above the host's
+// nyquist frequency are filtered out instead of aliasing into the audible
+// range. the result goes through the console's 90Hz and 440Hz high-pass and
+// 14kHz low-pass filters.
+pub struct Mixer {
+    pulse_table: [f32; 31],
+    tnd_table: [f32; 203],
+    sample_rate: u32,
+    cpu_clock: u32,
+    cycles_per_sample: f64,
+    // cpu cycles into the current output sample
+    phase: f64,
+    steps: Vec<[f32; STEP_WIDTH]>,
+    // mixed level of the previous cycle
+    level: f32,
+    // pending sample differences, a ring starting at `head`
+    pending: [f32; STEP_WIDTH],
+    head: usize,
+    // running sum of the differences, the band-limited level
+    out: f32,
+    filters: [Filter; 3],
+    samples: Vec<f32>,
+}
+
+impl Mixer {
+    // `cpu_clock` is the rate in Hz push is called at
+    pub fn new(sample_rate: u32, cpu_clock: u32) -> Self {
+        let mut pulse_table = [0.0; 31];
+        for (n, v) in pulse_table.iter_mut().enumerate().skip(1) {
+            *v = 95.52 / (8128.0 / n as f32 + 100.0);
+        }
+        let mut tnd_table = [0.0; 203];
+        for (n, v) in tnd_table.iter_mut().enumerate().skip(1) {
+            *v = 163.67 / (24329.0 / n as f32 + 100.0);
+        }
+        Mixer {
+            pulse_table: pulse_table,
+            tnd_table: tnd_table,
+            sample_rate: sample_rate,
+            cpu_clock: cpu_clock,
+            cycles_per_sample: cpu_clock as f64 / sample_rate as f64,
+            phase: 0.0,
+            steps: step_table(),
+            level: 0.0,
+            pending: [0.0; STEP_WIDTH],
+            head: 0,
+            out: 0.0,
+            filters: [
+                Filter::new(true, 90.0, sample_rate),
+                Filter::new(true, 440.0, sample_rate),
+                Filter::new(false, 14000.0, sample_rate),
+            ],
+            samples: Vec::with_capacity(sample_rate as usize / 30),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn cpu_clock(&self) -> u32 {
+        self.cpu_clock
+    }
+
+    // one cpu cycle of channel output
+    pub fn push(&mut self, pulse: (u8, u8), tnd: (u8, u8, u8)) {
+        let (triangle, noise, dmc) = tnd;
+        let level = self.pulse_table[(pulse.0 + pulse.1) as usize]
+            + self.tnd_table[3 * triangle as usize + 2 * noise as usize + dmc as usize];
+        let delta = level - self.level;
+        if delta != 0.0 {
+            self.level = level;
+            let pos = self.phase / self.cycles_per_sample * STEP_PHASES as f64;
+            let phase = pos as usize;
+            let t = (pos - phase as f64) as f32;
+            let (a, b) = (&self.steps[phase], &self.steps[phase + 1]);
+            for (i, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+                self.pending[(self.head + i) % STEP_WIDTH] += delta * (a + (b - a) * t);
+            }
+        }
+        self.phase += 1.0;
+        if self.phase < self.cycles_per_sample {
+            return;
+        }
+        self.phase -= self.cycles_per_sample;
+
+        self.out += std::mem::take(&mut self.pending[self.head]);
+        self.head = (self.head + 1) % STEP_WIDTH;
+        let mut x = self.out;
+        for filter in self.filters.iter_mut() {
+            x = filter.process(x);
+        }
+        // nobody drains the buffer. keep it under a second by dropping the oldest tenth.
+        if self.samples.len() >= self.sample_rate as usize {
+            let n = self.sample_rate as usize / 10;
+            self.samples.drain(..n);
+            debug!("audio buffer full, dropped {} samples", n);
+        }
+        self.samples.push(x);
+    }
+
+    pub fn drain(&mut self, sink: &mut dyn SampleSink) {
+        sink.append(&self.samples);
+        self.samples.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CPU_CLOCK: u32 = 1_789_773;
+
+    // rms of a full volume pulse 1 square wave that flips every `half_period`
+    // cycles, over half a second once the filters settled
+    fn square_rms(half_period: u32) -> f32 {
+        let mut mixer = Mixer::new(DEFAULT_SAMPLE_RATE, CPU_CLOCK);
+        for cycle in 0..CPU_CLOCK / 2 {
+            let volume = if (cycle / half_period) % 2 == 1 { 15 } else { 0 };
+            mixer.push((volume, 0), (0, 0, 0));
+        }
+        let mut out: Vec<f32> = vec![];
+        mixer.drain(&mut out);
+        let tail = &out[DEFAULT_SAMPLE_RATE as usize / 10..];
+        (tail.iter().map(|s| s * s).sum::<f32>() / tail.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn no_aliasing_above_nyquist() {
+        // 895Hz is audible, 29.8kHz and its harmonics are all above 22050Hz
+        let audible = square_rms(1000);
+        let ultrasonic = square_rms(30);
+        assert!(audible > 0.05);
+        assert!(ultrasonic < audible / 1000.0, "{} vs {}", ultrasonic, audible);
+    }
+
+    #[test]
+    fn full_buffer_drops_the_oldest() {
+        let mut mixer = Mixer::new(DEFAULT_SAMPLE_RATE, CPU_CLOCK);
+        for cycle in 0..CPU_CLOCK * 3 / 2 {
+            // silent for the first second, loud after it
+            let volume = if cycle < CPU_CLOCK { 0 } else { 15 };
+            mixer.push((volume, 0), (0, 0, 0));
+        }
+        let mut out: Vec<f32> = vec![];
+        mixer.drain(&mut out);
+        assert!(out.len() <= DEFAULT_SAMPLE_RATE as usize);
+        assert!(out.len() >= DEFAULT_SAMPLE_RATE as usize * 9 / 10);
+        // the newest samples, with the step, are the ones kept
+        assert!(out[out.len() / 2..].iter().any(|&s| s > 0.1));
+    }
+}
@@ -0,0 +1,443 @@
+use crate::cart::{RomError, Timing};
+use crate::cpu::Cpu;
+use crate::emu::Emu;
+use crate::mapper::NsfMapper;
+use crate::ops::stack::push_u16;
+use log::{debug, warn};
+
+const NSF_MAGIC: [u8; 5] = [0x4e, 0x45, 0x53, 0x4d, 0x1a];
+const NSFE_MAGIC: [u8; 4] = [0x4e, 0x53, 0x46, 0x45];
+const NSF_HEADER_SIZE: usize = 0x80;
+// 1/60.1 s, the usual ntsc play rate
+const DEFAULT_NTSC_SPEED: u16 = 16639;
+// 1/50.007 s
+const DEFAULT_PAL_SPEED: u16 = 19997;
+// INIT and PLAY return here through rts. the cpu is stopped before it fetches from it.
+const NSF_RETURN: u16 = 0x5ff0;
+
+// nsf / nsfe music rip
+pub struct Nsf {
+    pub songs: u8,
+    // 1 based
+    pub start_song: u8,
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+    pub name: String,
+    pub artist: String,
+    pub copyright: String,
+    // play routine period in microseconds
+    pub ntsc_speed: u16,
+    pub pal_speed: u16,
+    // bit 0: pal, bit 1: dual region
+    pub region: u8,
+    // expansion audio chips. they are not emulated.
+    pub extra_chips: u8,
+    // initial $5ff8-$5fff values. all zero means no bank switching.
+    pub banks: [u8; 8],
+    pub data: Vec<u8>,
+}
+
+impl Nsf {
+    pub fn is_nsf(raw: &[u8]) -> bool {
+        raw.starts_with(&NSF_MAGIC) || raw.starts_with(&NSFE_MAGIC)
+    }
+
+    pub fn from_raw(raw: &[u8]) -> Result<Nsf, RomError> {
+        if raw.starts_with(&NSF_MAGIC) {
+            Nsf::from_nsf(raw)
+        } else if raw.starts_with(&NSFE_MAGIC) {
+            Nsf::from_nsfe(raw)
+        } else {
+            Err(RomError::BAD_MAGIC)
+        }
+    }
+
+    pub fn bankswitched(&self) -> bool {
+        self.banks.iter().any(|&b| b != 0)
+    }
+
+    // pal only. dual region files are played at the ntsc rate.
+    pub fn pal(&self) -> bool {
+        self.region & 0b11 == 0b01
+    }
+
+    // the console the tune is played on
+    pub fn timing(&self) -> Timing {
+        if self.pal() {
+            Timing::PAL
+        } else {
+            Timing::NTSC
+        }
+    }
+
+    // play routine period in microseconds
+    pub fn play_speed(&self) -> u16 {
+        match (self.pal(), self.pal_speed, self.ntsc_speed) {
+            (true, 0, _) => DEFAULT_PAL_SPEED,
+            (true, speed, _) => speed,
+            (false, _, 0) => DEFAULT_NTSC_SPEED,
+            (false, _, speed) => speed,
+        }
+    }
+
+    fn from_nsf(raw: &[u8]) -> Result<Nsf, RomError> {
+        if raw.len() < NSF_HEADER_SIZE {
+            return Err(RomError::TRUNCATED_HEADER);
+        }
+        let mut banks = [0; 8];
+        banks.copy_from_slice(&raw[0x70..0x78]);
+        Ok(Nsf {
+            songs: raw[0x06],
+            start_song: raw[0x07],
+            load_addr: u16_at(raw, 0x08),
+            init_addr: u16_at(raw, 0x0a),
+            play_addr: u16_at(raw, 0x0c),
+            name: string_at(&raw[0x0e..0x2e]),
+            artist: string_at(&raw[0x2e..0x4e]),
+            copyright: string_at(&raw[0x4e..0x6e]),
+            ntsc_speed: u16_at(raw, 0x6e),
+            pal_speed: u16_at(raw, 0x78),
+            region: raw[0x7a],
+            extra_chips: raw[0x7b],
+            banks: banks,
+            data: raw[NSF_HEADER_SIZE..].to_vec(),
+        })
+    }
+
+    // nsfe is a list of chunks: u32 length, 4 byte id, data. ids starting with
+    // an upper case letter must be understood, the rest can be skipped.
+    fn from_nsfe(raw: &[u8]) -> Result<Nsf, RomError> {
+        let mut nsf = Nsf {
+            songs: 1,
+            start_song: 1,
+            load_addr: 0,
+            init_addr: 0,
+            play_addr: 0,
+            name: String::new(),
+            artist: String::new(),
+            copyright: String::new(),
+            ntsc_speed: DEFAULT_NTSC_SPEED,
+            pal_speed: 0,
+            region: 0,
+            extra_chips: 0,
+            banks: [0; 8],
+            data: vec![],
+        };
+        let mut has_info = false;
+        let mut pos = NSFE_MAGIC.len();
+        loop {
+            if raw.len() < pos + 8 {
+                return Err(RomError::TRUNCATED_HEADER);
+            }
+            let len = u32::from_le_bytes([raw[pos], raw[pos + 1], raw[pos + 2], raw[pos + 3]]) as usize;
+            let id = &raw[pos + 4..pos + 8];
+            pos += 8;
+            if raw.len() - pos < len {
+                return Err(RomError::UNSUPPORTED_FORMAT("nsfe chunk runs past the end of the file"));
+            }
+            let chunk = &raw[pos..pos + len];
+            pos += len;
+            match id {
+                b"INFO" => {
+                    if chunk.len() < 8 {
+                        return Err(RomError::UNSUPPORTED_FORMAT("nsfe INFO chunk is too short"));
+                    }
+                    nsf.load_addr = u16_at(chunk, 0);
+                    nsf.init_addr = u16_at(chunk, 2);
+                    nsf.play_addr = u16_at(chunk, 4);
+                    nsf.region = chunk[6];
+                    nsf.extra_chips = chunk[7];
+                    nsf.songs = chunk.get(8).copied().unwrap_or(1);
+                    // nsfe counts songs from 0
+                    nsf.start_song = chunk.get(9).copied().unwrap_or(0) + 1;
+                    has_info = true;
+                }
+                b"DATA" => nsf.data = chunk.to_vec(),
+                b"BANK" => {
+                    let n = chunk.len().min(8);
+                    nsf.banks[..n].copy_from_slice(&chunk[..n]);
+                }
+                // ntsc rate, then the pal rate and an optional dendy rate
+                b"RATE" => {
+                    if chunk.len() >= 2 {
+                        nsf.ntsc_speed = u16_at(chunk, 0);
+                    }
+                    if chunk.len() >= 4 {
+                        nsf.pal_speed = u16_at(chunk, 2);
+                    }
+                }
+                b"auth" => {
+                    let mut fields = chunk.split(|&b| b == 0).map(string_at);
+                    nsf.name = fields.next().unwrap_or_default();
+                    nsf.artist = fields.next().unwrap_or_default();
+                    nsf.copyright = fields.next().unwrap_or_default();
+                }
+                b"NEND" => break,
+                id if id[0].is_ascii_uppercase() => {
+                    return Err(RomError::UNSUPPORTED_FORMAT("unknown required nsfe chunk"));
+                }
+                _ => {}
+            }
+        }
+        if !has_info || nsf.data.is_empty() {
+            return Err(RomError::UNSUPPORTED_FORMAT("nsfe without INFO or DATA chunk"));
+        }
+        Ok(nsf)
+    }
+}
+
+fn u16_at(raw: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([raw[offset], raw[offset + 1]])
+}
+
+// zero terminated text field
+fn string_at(raw: &[u8]) -> String {
+    let end = raw.iter().position(|&b| b == 0).unwrap_or(raw.len());
+    String::from_utf8_lossy(&raw[..end]).into_owned()
+}
+
+impl Emu {
+    pub fn load_nsf(&mut self, nsf: &Nsf) -> Result<(), RomError> {
+        if nsf.extra_chips != 0 {
+            debug!("nsf uses expansion audio {:02x}, which is not emulated", nsf.extra_chips);
+        }
+        let cart = NsfMapper::new(nsf)?;
+        self.mem.set_cart(Box::new(cart));
+        self.set_timing(nsf.timing());
+        self.cpu = Cpu::default();
+        self.stat.cycle_counter = 0;
+        Ok(())
+    }
+
+    // set up the machine for `song` (0 based) and run INIT
+    pub fn nsf_init(&mut self, nsf: &Nsf, song: u8) {
+        self.mem.bus.ram = [0; 0x800];
+        if let Some(ram) = self.mem.bus.cart.prg_ram() {
+            ram.fill(0);
+        }
+        for addr in 0x4000..=0x4013 {
+            self.mem.store_u8(addr, 0);
+        }
+        self.mem.store_u8(0x4015, 0x0f);
+        self.mem.store_u8(0x4017, 0x40);
+        if nsf.bankswitched() {
+            for (i, &bank) in nsf.banks.iter().enumerate() {
+                self.mem.store_u8(0x5ff8 + i as u16, bank);
+            }
+        }
+        self.cpu = Cpu::default();
+        self.cpu.a = song;
+        // 0 for ntsc, 1 for pal
+        self.cpu.x = nsf.pal() as u8;
+        self.nsf_call(nsf.init_addr);
+    }
+
+    // run PLAY once and let the apu run until the next call is due
+    pub fn nsf_play(&mut self, nsf: &Nsf) {
+        let start = self.stat.cycle_counter;
+        self.nsf_call(nsf.play_addr);
+        let clock = nsf.timing().cpu_clock() as u64;
+        let period = (nsf.play_speed() as u64 * clock / 1_000_000) as u32;
+        let spent = self.stat.cycle_counter - start;
+        if spent < period {
+            self.mem.bus.tick(period - spent);
+            self.stat.cycle_counter += period - spent;
+        }
+    }
+
+    // jsr to `addr` and run until it returns, giving up after a second.
+    // the stack pointer is put back even when the routine never returned.
+    fn nsf_call(&mut self, addr: u16) {
+        let s = self.cpu.s;
+        push_u16(self, NSF_RETURN - 1);
+        self.cpu.pc = addr;
+        let limit = self.stat.cycle_counter + self.mem.bus.apu.mixer.cpu_clock();
+        while self.cpu.pc != NSF_RETURN && self.stat.cycle_counter < limit && !self.cpu.jammed {
+            self.run_cpu_once();
+        }
+        if self.cpu.jammed {
+            warn!("nsf routine at {:04x} jammed the cpu", addr);
+        } else if self.cpu.pc != NSF_RETURN {
+            warn!("nsf routine at {:04x} did not return within a second", addr);
+        }
+        self.cpu.s = s;
+    }
+
+    // render `plays` calls of PLAY for `song` (0 based)
+    pub fn record_nsf(&mut self, nsf: &Nsf, song: u8, plays: u32) -> Vec<i16> {
+        let mut samples: Vec<i16> = vec![];
+        self.nsf_init(nsf, song);
+        self.drain_samples(&mut samples);
+        samples.clear();
+        for _ in 0..plays {
+            self.nsf_play(nsf);
+            self.drain_samples(&mut samples);
+        }
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::image;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        image(8, &[(0, &(data.len() as u32).to_le_bytes()), (4, id)], data)
+    }
+
+    // init: stx $00, rts. play: rts.
+    const CODE: [u8; 4] = [0x86, 0x00, 0x60, 0x60];
+
+    fn nsf_file(region: u8, code: &[u8]) -> Vec<u8> {
+        image(NSF_HEADER_SIZE, &[
+            (0x00, &NSF_MAGIC),
+            (0x06, &[3, 2]),
+            (0x08, &[0x00, 0x80, 0x00, 0x80, 0x03, 0x80]),
+            (0x0e, b"Title"),
+            (0x2e, b"Artist"),
+            (0x4e, b"1986"),
+            (0x6e, &16639u16.to_le_bytes()),
+            (0x70, &[0, 1, 2, 3, 4, 5, 6, 7]),
+            (0x78, &20000u16.to_le_bytes()),
+            (0x7a, &[region]),
+        ], code)
+    }
+
+    #[test]
+    fn nsf_header() {
+        let nsf = Nsf::from_raw(&nsf_file(0, &CODE)).unwrap();
+        assert_eq!((nsf.songs, nsf.start_song), (3, 2));
+        assert_eq!((nsf.load_addr, nsf.init_addr, nsf.play_addr), (0x8000, 0x8000, 0x8003));
+        assert_eq!((nsf.name.as_str(), nsf.artist.as_str(), nsf.copyright.as_str()), ("Title", "Artist", "1986"));
+        assert_eq!((nsf.ntsc_speed, nsf.pal_speed), (16639, 20000));
+        assert!(nsf.bankswitched());
+        assert_eq!(nsf.data, CODE);
+        assert!(!nsf.pal());
+        assert_eq!(nsf.play_speed(), 16639);
+
+        assert_eq!(Nsf::from_raw(&nsf_file(0, &CODE)[..0x7f]).err(), Some(RomError::TRUNCATED_HEADER));
+        assert_eq!(Nsf::from_raw(b"NES\x1a").err(), Some(RomError::BAD_MAGIC));
+    }
+
+    #[test]
+    fn region() {
+        let pal = Nsf::from_raw(&nsf_file(1, &CODE)).unwrap();
+        assert!(pal.pal());
+        assert_eq!(pal.play_speed(), 20000);
+        // dual region plays at the ntsc rate
+        let dual = Nsf::from_raw(&nsf_file(3, &CODE)).unwrap();
+        assert!(!dual.pal());
+        assert_eq!(dual.play_speed(), 16639);
+
+        let mut raw = nsf_file(1, &CODE);
+        raw[0x78..0x7a].copy_from_slice(&[0, 0]);
+        assert_eq!(Nsf::from_raw(&raw).unwrap().play_speed(), DEFAULT_PAL_SPEED);
+    }
+
+    fn nsfe_file(chunks: &[Vec<u8>]) -> Vec<u8> {
+        image(NSFE_MAGIC.len(), &[(0, &NSFE_MAGIC)], &chunks.concat())
+    }
+
+    fn info(region: u8) -> Vec<u8> {
+        chunk(b"INFO", &[0x00, 0x80, 0x00, 0x80, 0x03, 0x80, region, 0x00, 4, 1])
+    }
+
+    #[test]
+    fn nsfe_chunks() {
+        let raw = nsfe_file(&[
+            info(1),
+            chunk(b"DATA", &CODE),
+            chunk(b"BANK", &[0, 1, 2]),
+            chunk(b"RATE", &[0x10, 0x41, 0x20, 0x4e, 0x00, 0x50]),
+            chunk(b"auth", b"Title\0Artist\0\0Ripper"),
+            chunk(b"tlbl", b"skipped"),
+            chunk(b"NEND", &[]),
+        ]);
+        let nsf = Nsf::from_raw(&raw).unwrap();
+        assert_eq!((nsf.load_addr, nsf.init_addr, nsf.play_addr), (0x8000, 0x8000, 0x8003));
+        assert_eq!((nsf.songs, nsf.start_song), (4, 2));
+        assert_eq!(nsf.data, CODE);
+        assert_eq!(nsf.banks, [0, 1, 2, 0, 0, 0, 0, 0]);
+        assert_eq!((nsf.ntsc_speed, nsf.pal_speed), (0x4110, 0x4e20));
+        assert_eq!((nsf.name.as_str(), nsf.artist.as_str(), nsf.copyright.as_str()), ("Title", "Artist", ""));
+        assert!(nsf.pal());
+        assert_eq!(nsf.play_speed(), 0x4e20);
+
+        // a 2 byte RATE only has the ntsc rate
+        let raw = nsfe_file(&[info(0), chunk(b"DATA", &CODE), chunk(b"RATE", &[0x10, 0x41]), chunk(b"NEND", &[])]);
+        let nsf = Nsf::from_raw(&raw).unwrap();
+        assert_eq!((nsf.ntsc_speed, nsf.pal_speed), (0x4110, 0));
+    }
+
+    #[test]
+    fn nsfe_errors() {
+        let err = |chunks: &[Vec<u8>]| Nsf::from_raw(&nsfe_file(chunks)).err();
+        assert_eq!(err(&[info(0), chunk(b"DATA", &CODE)]), Some(RomError::TRUNCATED_HEADER));
+        assert_eq!(err(&[chunk(b"INFO", &[0; 7]), chunk(b"NEND", &[])]),
+                   Some(RomError::UNSUPPORTED_FORMAT("nsfe INFO chunk is too short")));
+        assert_eq!(err(&[info(0), chunk(b"DATA", &CODE), chunk(b"VRC7", &[0]), chunk(b"NEND", &[])]),
+                   Some(RomError::UNSUPPORTED_FORMAT("unknown required nsfe chunk")));
+        assert_eq!(err(&[info(0), chunk(b"NEND", &[])]),
+                   Some(RomError::UNSUPPORTED_FORMAT("nsfe without INFO or DATA chunk")));
+        let mut raw = nsfe_file(&[info(0), chunk(b"DATA", &CODE)]);
+        raw.truncate(raw.len() - 1);
+        assert_eq!(Nsf::from_raw(&raw).err(),
+                   Some(RomError::UNSUPPORTED_FORMAT("nsfe chunk runs past the end of the file")));
+    }
+
+    // init: jmp $8004. play: rts. $8004: stx $00, a 50% duty pulse at full
+    // volume with timer period $0fd, rts.
+    const TONE: [u8; 22] = [
+        0x4c, 0x04, 0x80, 0x60, 0x86, 0x00, 0xa9, 0xbf, 0x8d, 0x00, 0x40,
+        0xa9, 0xfd, 0x8d, 0x02, 0x40, 0xa9, 0x00, 0x8d, 0x03, 0x40, 0x60,
+    ];
+
+    // power of `samples` at `freq` Hz
+    fn power(samples: &[i16], freq: f64) -> f64 {
+        let w = 2.0 * std::f64::consts::PI * freq / crate::mixer::DEFAULT_SAMPLE_RATE as f64;
+        let (re, im) = samples.iter().enumerate().fold((0.0, 0.0), |(re, im), (i, &s)| {
+            (re + s as f64 * (w * i as f64).cos(), im + s as f64 * (w * i as f64).sin())
+        });
+        (re * re + im * im) / samples.len() as f64
+    }
+
+    #[test]
+    fn pal_init_and_play_rate() {
+        let nsf = Nsf::from_raw(&nsf_file(1, &TONE)).unwrap();
+        let mut emu = Emu::default();
+        emu.load_nsf(&nsf).unwrap();
+        emu.nsf_init(&nsf, 0);
+        // init stored X
+        assert_eq!(emu.mem.bus.ram[0], 1);
+        // 20000us at the pal clock
+        let start = emu.stat.cycle_counter;
+        emu.nsf_play(&nsf);
+        assert_eq!(emu.stat.cycle_counter - start, (20000 * Timing::PAL.cpu_clock() as u64 / 1_000_000) as u32);
+        // the tone comes out at the pitch of a pal console, 1662607 / (16 * 254) Hz,
+        // not at the ntsc 1789773 / (16 * 254) Hz
+        let samples = emu.record_nsf(&nsf, 0, 50);
+        assert!(power(&samples, 409.1) > 100.0 * power(&samples, 440.4));
+
+        let nsf = Nsf::from_raw(&nsf_file(0, &TONE)).unwrap();
+        emu.load_nsf(&nsf).unwrap();
+        emu.nsf_init(&nsf, 0);
+        assert_eq!(emu.mem.bus.ram[0], 0);
+        let samples = emu.record_nsf(&nsf, 0, 60);
+        assert!(power(&samples, 440.4) > 100.0 * power(&samples, 409.1));
+    }
+
+    #[test]
+    fn stack_pointer_survives_a_stuck_routine() {
+        // init: jmp *. play: a jam opcode.
+        let nsf = Nsf::from_raw(&nsf_file(0, &[0x4c, 0x00, 0x80, 0x02])).unwrap();
+        let mut emu = Emu::default();
+        emu.load_nsf(&nsf).unwrap();
+        emu.nsf_init(&nsf, 0);
+        assert_eq!(emu.cpu.s, Cpu::default().s);
+        emu.nsf_play(&nsf);
+        assert!(emu.cpu.jammed);
+        assert_eq!(emu.cpu.s, Cpu::default().s);
+    }
+}
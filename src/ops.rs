@@ -1,141 +1,168 @@
 #![cfg_attr(rustfmt, rustfmt_skip)]
 
 use std::ops::{Shl, Not};
-use crate::cpu::Flags;
+use crate::cpu::{Flags, IRQ_VECTOR};
 use crate::emu::Emu;
+use crate::unofficial_ops::*;
 
 pub type Behaviour = fn(&mut Emu);
 
 #[rustfmt::skip]
 pub const OP_FUNC: [Behaviour; 256] = 
-//        0       1      2         3      4        5       6         7      8     9      A        B      C        D       E         F
-[/*0*/ brk,     ora_inx, undef,   undef, undef,   ora_zpg, asl_zpg, undef, php_i, ora_imm, asl_i, undef, undef,   ora_abs, asl_abs, undef,
- /*1*/ bpl_rel, ora_iny, undef,   undef, undef,   ora_zpx, asl_zpx, undef, clc_i, ora_aby, undef, undef, undef,   ora_abx, asl_abx, undef,
- /*2*/ jsr_abs, and_inx, undef,   undef, bit_zpg, and_zpg, rol_zpg, undef, plp_i, and_imm, rol_i, undef, bit_abs, and_abs, rol_abs, undef,
- /*3*/ bmi_rel, and_iny, undef,   undef, undef,   and_zpx, rol_zpx, undef, sec_i, and_aby, undef, undef, undef,   and_abx, rol_abx, undef,
- /*4*/ rti_i,   eor_inx, undef,   undef, undef,   eor_zpg, lsr_zpg, undef, pha_i, eor_imm, lsr_i, undef, jmp_abs, eor_abs, lsr_abs, undef,
- /*5*/ bvc_rel, eor_iny, undef,   undef, undef,   eor_zpx, lsr_zpx, undef, cli_i, eor_aby, undef, undef, undef,   eor_abx, lsr_abx, undef,
- /*6*/ rts_i,   adc_inx, undef,   undef, undef,   adc_zpg, ror_zpg, undef, pla_i, adc_imm, ror_i, undef, jmp_ind, adc_abs, ror_abs, undef,
- /*7*/ bvs_rel, adc_iny, undef,   undef, undef,   adc_zpx, ror_zpx, undef, sei_i, adc_aby, undef, undef, undef,   adc_abx, ror_abx, undef,
- /*8*/ undef,   sta_inx, undef,   undef, sty_zpg, sta_zpg, stx_zpg, undef, dey_i, undef,   txa_i, undef, sty_abs, sta_abs, stx_abs, undef,
- /*9*/ bcc_rel, sta_iny, undef,   undef, sty_zpx, sta_zpx, stx_zpy, undef, tya_i, sta_aby, txs_i, undef, undef,   sta_abx, undef,   undef,
- /*A*/ ldy_imm, lda_inx, ldx_imm, undef, ldy_zpg, lda_zpg, ldx_zpg, undef, tay_i, lda_imm, tax_i, undef, ldy_abs, lda_abs, ldx_abs, undef,
- /*B*/ bcs_rel, lda_iny, undef,   undef, ldy_zpx, lda_zpx, ldx_zpy, undef, clv_i, lda_aby, tsx_i, undef, ldy_abx, lda_abx, ldx_aby, undef,
- /*C*/ cpy_imm, cmp_inx, undef,   undef, cpy_zpg, cmp_zpg, dec_zpg, undef, iny_i, cmp_imm, dex_i, undef, cpy_abs, cmp_abs, dec_abs, undef,
- /*D*/ bne_rel, cmp_iny, undef,   undef, undef,   cmp_zpx, dec_zpx, undef, cld_i, cmp_aby, undef, undef, undef,   cmp_abx, dec_abx, undef,
- /*E*/ cpx_imm, sbc_inx, undef,   undef, cpx_zpg, sbc_zpg, inc_zpg, undef, inx_i, sbc_imm, nop,   undef, cpx_abs, sbc_abs, inc_abs, undef,
- /*F*/ beq_rel, sbc_iny, undef,   undef, undef,   sbc_zpx, inc_zpx, undef, sed_i, sbc_aby, undef, undef, undef,   sbc_abx, inc_abx, undef
+//     0        1        2        3        4        5        6        7        8      9        A      B        C        D        E        F
+[/*0*/ brk,     ora_inx, kil,     slo_inx, dop_zpg, ora_zpg, asl_zpg, slo_zpg, php_i, ora_imm, asl_i, aac_imm, top_abs, ora_abs, asl_abs, slo_abs,
+ /*1*/ bpl_rel, ora_iny, kil,     slo_iny, dop_zpx, ora_zpx, asl_zpx, slo_zpx, clc_i, ora_aby, nop,   slo_aby, top_abx, ora_abx, asl_abx, slo_abx,
+ /*2*/ jsr_abs, and_inx, kil,     rla_inx, bit_zpg, and_zpg, rol_zpg, rla_zpg, plp_i, and_imm, rol_i, aac_imm, bit_abs, and_abs, rol_abs, rla_abs,
+ /*3*/ bmi_rel, and_iny, kil,     rla_iny, dop_zpx, and_zpx, rol_zpx, rla_zpx, sec_i, and_aby, nop,   rla_aby, top_abx, and_abx, rol_abx, rla_abx,
+ /*4*/ rti_i,   eor_inx, kil,     sre_inx, dop_zpg, eor_zpg, lsr_zpg, sre_zpg, pha_i, eor_imm, lsr_i, asr_imm, jmp_abs, eor_abs, lsr_abs, sre_abs,
+ /*5*/ bvc_rel, eor_iny, kil,     sre_iny, dop_zpx, eor_zpx, lsr_zpx, sre_zpx, cli_i, eor_aby, nop,   sre_aby, top_abx, eor_abx, lsr_abx, sre_abx,
+ /*6*/ rts_i,   adc_inx, kil,     rra_inx, dop_zpg, adc_zpg, ror_zpg, rra_zpg, pla_i, adc_imm, ror_i, arr_imm, jmp_ind, adc_abs, ror_abs, rra_abs,
+ /*7*/ bvs_rel, adc_iny, kil,     rra_iny, dop_zpx, adc_zpx, ror_zpx, rra_zpx, sei_i, adc_aby, nop,   rra_aby, top_abx, adc_abx, ror_abx, rra_abx,
+ /*8*/ dop_imm, sta_inx, dop_imm, aax_inx, sty_zpg, sta_zpg, stx_zpg, aax_zpg, dey_i, dop_imm, txa_i, xaa_imm, sty_abs, sta_abs, stx_abs, aax_abs,
+ /*9*/ bcc_rel, sta_iny, kil,     axa_iny, sty_zpx, sta_zpx, stx_zpy, aax_zpy, tya_i, sta_aby, txs_i, xas_aby, sya_abx, sta_abx, sxa_aby, axa_aby,
+ /*A*/ ldy_imm, lda_inx, ldx_imm, lax_inx, ldy_zpg, lda_zpg, ldx_zpg, lax_zpg, tay_i, lda_imm, tax_i, atx_imm, ldy_abs, lda_abs, ldx_abs, lax_abs,
+ /*B*/ bcs_rel, lda_iny, kil,     lax_iny, ldy_zpx, lda_zpx, ldx_zpy, lax_zpy, clv_i, lda_aby, tsx_i, lar_aby, ldy_abx, lda_abx, ldx_aby, lax_aby,
+ /*C*/ cpy_imm, cmp_inx, dop_imm, dcp_inx, cpy_zpg, cmp_zpg, dec_zpg, dcp_zpg, iny_i, cmp_imm, dex_i, axs_imm, cpy_abs, cmp_abs, dec_abs, dcp_abs,
+ /*D*/ bne_rel, cmp_iny, kil,     dcp_iny, dop_zpx, cmp_zpx, dec_zpx, dcp_zpx, cld_i, cmp_aby, nop,   dcp_aby, top_abx, cmp_abx, dec_abx, dcp_abx,
+ /*E*/ cpx_imm, sbc_inx, dop_imm, isc_inx, cpx_zpg, sbc_zpg, inc_zpg, isc_zpg, inx_i, sbc_imm, nop,   sbc_imm, cpx_abs, sbc_abs, inc_abs, isc_abs,
+ /*F*/ beq_rel, sbc_iny, kil,     isc_iny, dop_zpx, sbc_zpx, inc_zpx, isc_zpx, sed_i, sbc_aby, nop,   isc_aby, top_abx, sbc_abx, inc_abx, isc_abx
 ];
 #[rustfmt::skip]
 pub const OP_CYCLE: [u8; 256] = [
-2, 6, 0, 0, 0, 3, 5, 0, 3, 2, 2, 0, 0, 4, 6, 0,
-2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0,
-6, 6, 0, 0, 3, 3, 5, 0, 4, 2, 2, 0, 4, 4, 6, 0,
-2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0,
-6, 6, 0, 0, 0, 3, 5, 0, 2, 2, 2, 0, 4, 4, 6, 0,
-2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0,
-6, 6, 0, 0, 0, 3, 5, 0, 2, 2, 2, 0, 3, 4, 6, 0,
-2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0,
-6, 6, 0, 0, 3, 3, 3, 0, 2, 2, 2, 0, 3, 4, 4, 0,
-2, 6, 0, 0, 4, 4, 4, 0, 2, 5, 2, 0, 4, 4, 4, 0,
-2, 6, 0, 0, 3, 3, 5, 0, 2, 2, 2, 0, 3, 4, 4, 0,
-2, 5, 0, 0, 4, 4, 6, 0, 2, 4, 2, 0, 4, 4, 4, 0,
-2, 6, 0, 0, 3, 3, 5, 0, 2, 2, 2, 0, 3, 4, 4, 0,
-2, 5, 0, 0, 4, 4, 6, 0, 0, 4, 2, 0, 4, 4, 4, 0,
-2, 6, 0, 0, 3, 3, 5, 0, 2, 2, 2, 0, 3, 4, 4, 0,
-2, 5, 0, 0, 4, 4, 6, 0, 0, 4, 2, 0, 4, 4, 4, 0
+7, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 4, 4, 6, 6,
+2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 4, 4, 6, 6,
+2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+6, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 3, 4, 6, 6,
+2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 5, 4, 6, 6,
+2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
+2, 6, 2, 6, 4, 4, 4, 4, 2, 5, 2, 5, 5, 5, 5, 5,
+2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
+2, 5, 2, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4,
+2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7
+];
+// extra cycle taken by read instructions when the indexed address crosses a page.
+#[rustfmt::skip]
+pub const OP_PAGE_CYCLE: [u8; 256] = [
+0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0,
+0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0,
+0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0,
+0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0,
+0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+0, 1, 0, 1, 0, 0, 0, 0, 0, 1, 0, 1, 1, 1, 1, 1,
+0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0,
+0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0
 ];
 pub const OP_NAME: [&'static str; 256] = [
-"brk", "ora", "undef", "undef", "undef", "ora", "asl", "undef", "php", "ora", "asl", "undef", "undef", "ora", "asl", "undef",
-"bpl", "ora", "undef", "undef", "undef", "ora", "asl", "undef", "clc", "ora", "undef", "undef", "undef", "ora", "asl", "undef",
-"jsr", "and", "undef", "undef", "bit", "and", "rol", "undef", "plp", "and", "rol", "undef", "bit", "and", "rol", "undef",
-"bmi", "and", "undef", "undef", "undef", "and", "rol", "undef", "sec", "and", "undef", "undef", "undef", "and", "rol", "undef",
-"rti", "eor", "undef", "undef", "undef", "eor", "lsr", "undef", "pha", "eor", "lsr", "undef", "jmp", "eor", "lsr", "undef",
-"bvc", "eor", "undef", "undef", "undef", "eor", "lsr", "undef", "cli", "eor", "undef", "undef", "undef", "eor", "lsr", "undef",
-"rts", "adc", "undef", "undef", "undef", "adc", "ror", "undef", "pla", "adc", "ror", "undef", "jmp", "adc", "ror", "undef",
-"bvs", "adc", "undef", "undef", "undef", "adc", "ror", "undef", "sei", "adc", "undef", "undef", "undef", "adc", "ror", "undef",
-"undef", "sta", "undef", "undef", "sty", "sta", "stx", "undef", "dey", "undef", "txa", "undef", "sty", "sta", "stx", "undef",
-"bcc", "sta", "undef", "undef", "sty", "sta", "stx", "undef", "tya", "sta", "txs", "undef", "undef", "sta", "undef", "undef",
-"ldy", "lda", "ldx", "undef", "ldy", "lda", "ldx", "undef", "tay", "lda", "tax", "undef", "ldy", "lda", "ldx", "undef",
-"bcs", "lda", "undef", "undef", "ldy", "lda", "ldx", "undef", "clv", "lda", "tsx", "undef", "ldy", "lda", "ldx", "undef",
-"cpy", "cmp", "undef", "undef", "cpy", "cmp", "dec", "undef", "iny", "cmp", "dex", "undef", "cpy", "cmp", "dec", "undef",
-"bne", "cmp", "undef", "undef", "undef", "cmp", "dec", "undef", "cld", "cmp", "undef", "undef", "undef", "cmp", "dec", "undef",
-"cpx", "sbc", "undef", "undef", "cpx", "sbc", "inc", "undef", "inx", "sbc", "nop", "undef", "cpx", "sbc", "inc", "undef",
-"beq", "sbc", "undef", "undef", "undef", "sbc", "inc", "undef", "sed", "sbc", "undef", "undef", "undef", "sbc", "inc", "undef"
+"brk", "ora", "kil", "slo", "dop", "ora", "asl", "slo", "php", "ora", "asl", "aac", "top", "ora", "asl", "slo",
+"bpl", "ora", "kil", "slo", "dop", "ora", "asl", "slo", "clc", "ora", "nop", "slo", "top", "ora", "asl", "slo",
+"jsr", "and", "kil", "rla", "bit", "and", "rol", "rla", "plp", "and", "rol", "aac", "bit", "and", "rol", "rla",
+"bmi", "and", "kil", "rla", "dop", "and", "rol", "rla", "sec", "and", "nop", "rla", "top", "and", "rol", "rla",
+"rti", "eor", "kil", "sre", "dop", "eor", "lsr", "sre", "pha", "eor", "lsr", "asr", "jmp", "eor", "lsr", "sre",
+"bvc", "eor", "kil", "sre", "dop", "eor", "lsr", "sre", "cli", "eor", "nop", "sre", "top", "eor", "lsr", "sre",
+"rts", "adc", "kil", "rra", "dop", "adc", "ror", "rra", "pla", "adc", "ror", "arr", "jmp", "adc", "ror", "rra",
+"bvs", "adc", "kil", "rra", "dop", "adc", "ror", "rra", "sei", "adc", "nop", "rra", "top", "adc", "ror", "rra",
+"dop", "sta", "dop", "aax", "sty", "sta", "stx", "aax", "dey", "dop", "txa", "xaa", "sty", "sta", "stx", "aax",
+"bcc", "sta", "kil", "axa", "sty", "sta", "stx", "aax", "tya", "sta", "txs", "xas", "sya", "sta", "sxa", "axa",
+"ldy", "lda", "ldx", "lax", "ldy", "lda", "ldx", "lax", "tay", "lda", "tax", "atx", "ldy", "lda", "ldx", "lax",
+"bcs", "lda", "kil", "lax", "ldy", "lda", "ldx", "lax", "clv", "lda", "tsx", "lar", "ldy", "lda", "ldx", "lax",
+"cpy", "cmp", "dop", "dcp", "cpy", "cmp", "dec", "dcp", "iny", "cmp", "dex", "axs", "cpy", "cmp", "dec", "dcp",
+"bne", "cmp", "kil", "dcp", "dop", "cmp", "dec", "dcp", "cld", "cmp", "nop", "dcp", "top", "cmp", "dec", "dcp",
+"cpx", "sbc", "dop", "isc", "cpx", "sbc", "inc", "isc", "inx", "sbc", "nop", "sbc", "cpx", "sbc", "inc", "isc",
+"beq", "sbc", "kil", "isc", "dop", "sbc", "inc", "isc", "sed", "sbc", "nop", "isc", "top", "sbc", "inc", "isc"
 ];
 
 
 
-mod addressing {
+pub(crate) mod addressing {
     use log::debug;
 
     use crate::emu::Emu;
     #[inline] 
-    pub fn immediate (emu: &Emu) -> u16{ 
+    pub fn immediate (emu: &mut Emu) -> u16{ 
         let r =emu.cpu.pc + 1;
-        debug!("#${:02x}", emu.mem.load_u8(r));
+        debug!("#${:02x}", emu.mem.peek_u8(r));
         r
     }
     #[inline] 
-    pub fn zeropage(emu: &Emu) -> u16 { 
+    pub fn zeropage(emu: &mut Emu) -> u16 { 
       let r = emu.mem.load_u8(emu.cpu.pc+1) as u16;
-      debug!("${:02x} = {:02x}", r, emu.mem.load_u8(r));
+      debug!("${:02x} = {:02x}", r, emu.mem.peek_u8(r));
         r
     }
     #[inline]
-    pub fn zeropage_x(emu: &Emu) -> u16 {
+    pub fn zeropage_x(emu: &mut Emu) -> u16 {
       let r = emu.mem.load_u8(emu.cpu.pc+1).wrapping_add(emu.cpu.x) as u16;
-        debug!("${:02x},X @ {:02x} = {:02x}", emu.mem.load_u8(emu.cpu.pc+1), r, emu.mem.load_u8(r));
+        debug!("${:02x},X @ {:02x} = {:02x}", emu.mem.peek_u8(emu.cpu.pc+1), r, emu.mem.peek_u8(r));
         r
     }
     #[inline]
-    pub fn zeropage_y(emu: &Emu) -> u16 {
+    pub fn zeropage_y(emu: &mut Emu) -> u16 {
       let r = emu.mem.load_u8(emu.cpu.pc+1).wrapping_add(emu.cpu.y) as u16 ;
-        debug!("${:02x},Y @ {:02x} = {:02x}", emu.mem.load_u8(emu.cpu.pc+1), r, emu.mem.load_u8(r));
+        debug!("${:02x},Y @ {:02x} = {:02x}", emu.mem.peek_u8(emu.cpu.pc+1), r, emu.mem.peek_u8(r));
         r
     }
     #[inline] 
-    pub fn absolute(emu: &Emu) -> u16 {
+    pub fn absolute(emu: &mut Emu) -> u16 {
         let r = emu.mem.load_u16(emu.cpu.pc+1) ;
-        debug!("abs ${:04x} = {:04x} = {:04x}", emu.cpu.pc+1, r, emu.mem.load_u16(r));
+        debug!("abs ${:04x} = {:04x} = {:04x}", emu.cpu.pc+1, r, emu.mem.peek_u16(r));
         r
     }
     #[inline] 
-    pub fn absolute_x(emu: &Emu) -> u16 {
-        let r =  emu.mem.load_u16(emu.cpu.pc+1).wrapping_add(emu.cpu.x as u16) ;
-        debug!("${:04x},X @ {:04x} = {:02x}", emu.mem.load_u16(emu.cpu.pc+1), r, emu.mem.load_u8(r));
+    pub fn absolute_x(emu: &mut Emu) -> u16 {
+        let base = emu.mem.load_u16(emu.cpu.pc+1);
+        let r = base.wrapping_add(emu.cpu.x as u16);
+        emu.cpu.page_crossed = (base ^ r) & 0xFF00 != 0;
+        debug!("${:04x},X @ {:04x} = {:02x}", emu.mem.peek_u16(emu.cpu.pc+1), r, emu.mem.peek_u8(r));
         r
     }
     #[inline]
-    pub fn absolute_y(emu: &Emu) -> u16 {
-        let r = emu.mem.load_u16(emu.cpu.pc+1).wrapping_add(emu.cpu.y as u16);
-        debug!("${:04x},Y @ {:04x} = {:02x}", emu.mem.load_u16(emu.cpu.pc+1), r, emu.mem.load_u8(r));
+    pub fn absolute_y(emu: &mut Emu) -> u16 {
+        let base = emu.mem.load_u16(emu.cpu.pc+1);
+        let r = base.wrapping_add(emu.cpu.y as u16);
+        emu.cpu.page_crossed = (base ^ r) & 0xFF00 != 0;
+        debug!("${:04x},Y @ {:04x} = {:02x}", emu.mem.peek_u16(emu.cpu.pc+1), r, emu.mem.peek_u8(r));
         r
     }
     #[inline]
-    pub fn indirect_x(emu: &Emu) -> u16 {
+    pub fn indirect_x(emu: &mut Emu) -> u16 {
         let addr = emu.mem.load_u8(emu.cpu.pc+1).wrapping_add(emu.cpu.x);
         let r = emu.mem.load_u8(addr as u16) as u16 | ((emu.mem.load_u8(addr.wrapping_add(1) as u16) as u16) << 8) as u16;
-        debug!("(${:02x},X) @ {:02x} = {:04x} = {:02x}", emu.mem.load_u8(emu.cpu.pc+1), addr, r, emu.mem.load_u8(r));
+        debug!("(${:02x},X) @ {:02x} = {:04x} = {:02x}", emu.mem.peek_u8(emu.cpu.pc+1), addr, r, emu.mem.peek_u8(r));
         r
     }
     #[inline]
-    pub fn indirect_y(emu: &Emu) -> u16 {
+    pub fn indirect_y(emu: &mut Emu) -> u16 {
         let addr = emu.mem.load_u8(emu.cpu.pc+1);
         let r = emu.mem.load_u8(addr as u16) as u16 | ((emu.mem.load_u8(addr.wrapping_add(1) as u16) as u16) << 8) as u16;
-        let r = r.wrapping_add(emu.cpu.y as u16);
-        debug!("(${:02x}),Y = {:04x} @ {:04x} = {:02x}", emu.mem.load_u8(emu.cpu.pc+1), emu.mem.load_u16(addr as u16), r, emu.mem.load_u8(r));
+        let base = r;
+        let r = base.wrapping_add(emu.cpu.y as u16);
+        emu.cpu.page_crossed = (base ^ r) & 0xFF00 != 0;
+        debug!("(${:02x}),Y = {:04x} @ {:04x} = {:02x}", emu.mem.peek_u8(emu.cpu.pc+1), emu.mem.peek_u16(addr as u16), r, emu.mem.peek_u8(r));
         r
     }
     #[inline]
-    pub fn relative(emu: &Emu) -> u16 {
+    pub fn relative(emu: &mut Emu) -> u16 {
         let r = (emu.cpu.pc as i16 + 2 + ((emu.mem.load_u8(emu.cpu.pc+1) as i8) as i16)) as u16;
         debug!("${:04x}", r);
         r
     }
 } // mod addressing
 
-mod stack {
+pub(crate) mod stack {
 use crate::emu::Emu;
 #[inline]
 pub fn push_u8 (emu: &mut Emu, val: u8) {
@@ -164,21 +191,25 @@ fn branch(emu: &mut Emu, cond: bool) {
         let pc = emu.cpu.pc + 2;
         if cond {
             emu.cpu.pc = addressing::relative(emu);
-            emu.stat.cycle_counter += ((emu.cpu.pc ^ pc) & 0xFF00 != 0)  as u32;
+            emu.stat.cycle_counter += 1 + ((emu.cpu.pc ^ pc) & 0xFF00 != 0)  as u32;
         } else {
             emu.cpu.pc = pc;
         }
 }
 
-pub fn  undef(emu: &mut Emu) {
-    println!("undefined opcode {:04X} {:02X}", emu.cpu.pc, emu.mem.load_u8(emu.cpu.pc) );
-    emu.cpu.pc += 1;
-}
 pub fn  brk (emu: &mut Emu) {
-    stack::push_u16(emu, emu.cpu.pc + 1);
-    stack::push_u8(emu, emu.cpu.sp.bits());
+    stack::push_u16(emu, emu.cpu.pc + 2);
+    stack::push_u8(emu, emu.cpu.sp.bits() | Flags::A.bits() | Flags::B.bits());
+    emu.cpu.interrupt_flag(true);
+    emu.cpu.pc = emu.mem.load_u16(IRQ_VECTOR);
+}
+// hardware interrupt entry (nmi / irq). same as brk but pushes the current pc
+// and the flags with B cleared.
+pub fn  interrupt (emu: &mut Emu, vector: u16) {
+    stack::push_u16(emu, emu.cpu.pc);
+    stack::push_u8(emu, (emu.cpu.sp.bits() | Flags::A.bits()) & Flags::B.bits().not());
     emu.cpu.interrupt_flag(true);
-    emu.cpu.pc = emu.mem.load_u16(0xFFFE);
+    emu.cpu.pc = emu.mem.load_u16(vector);
 }
 pub fn nop (emu: &mut Emu) {
     emu.cpu.pc += 1;
@@ -198,12 +229,16 @@ pub fn  and(emu: &mut Emu, val: u8) {
     emu.cpu.a = emu.cpu.a & val;   
     emu.cpu.nz_flags(emu.cpu.a) 
 }
+// read-modify-write ops write the unmodified value back before the result,
+// like the real cpu. registers with write side effects (mmc1) see both writes.
 #[inline]
-pub fn  asl(emu: &mut Emu, addr: u16) {
+pub fn  asl(emu: &mut Emu, addr: u16) -> u8 {
     let val = emu.mem.load_u8(addr);
+    emu.mem.store_u8(addr, val);
     emu.cpu.carry_flag(val & 0x80 != 0);
     emu.mem.store_u8(addr, val << 1);
     emu.cpu.nz_flags(val << 1);
+    val << 1
 }
 pub fn  asl_i(emu: &mut Emu) {
     let val = emu.cpu.a;
@@ -264,10 +299,13 @@ pub fn  cpy (emu: &mut Emu, val: u8) {
     emu.cpu.nz_flags(r);
 }
 #[inline]
-pub fn  dec (emu: &mut Emu, addr: u16) {
-    let val = emu.mem.load_u8(addr).wrapping_sub(1);
+pub fn  dec (emu: &mut Emu, addr: u16) -> u8 {
+    let old = emu.mem.load_u8(addr);
+    emu.mem.store_u8(addr, old);
+    let val = old.wrapping_sub(1);
     emu.mem.store_u8(addr, val);
     emu.cpu.nz_flags(val);
+    val
 }
 #[inline]
 pub fn  eor (emu: &mut Emu, val: u8) {
@@ -275,17 +313,22 @@ pub fn  eor (emu: &mut Emu, val: u8) {
     emu.cpu.nz_flags(emu.cpu.a);
 }
 #[inline]
-pub fn  inc (emu: &mut Emu, addr: u16) {
-    let val = emu.mem.load_u8(addr).wrapping_add(1);
+pub fn  inc (emu: &mut Emu, addr: u16) -> u8 {
+    let old = emu.mem.load_u8(addr);
+    emu.mem.store_u8(addr, old);
+    let val = old.wrapping_add(1);
     emu.mem.store_u8(addr, val);
     emu.cpu.nz_flags(val);
+    val
 }
 #[inline]
-pub fn  lsr (emu: &mut Emu, addr: u16) {
+pub fn  lsr (emu: &mut Emu, addr: u16) -> u8 {
     let val = emu.mem.load_u8(addr);
+    emu.mem.store_u8(addr, val);
     emu.cpu.carry_flag(val & 0x01 != 0);
     emu.mem.store_u8(addr, val >> 1);
     emu.cpu.nz_flags(val >> 1);
+    val >> 1
 }
 #[inline]
 pub fn  ora(emu: &mut Emu, val: u8) { 
@@ -293,20 +336,24 @@ pub fn  ora(emu: &mut Emu, val: u8) {
     emu.cpu.nz_flags(emu.cpu.a) 
 }
 #[inline]
-pub fn  rol(emu: &mut Emu, addr: u16) {
+pub fn  rol(emu: &mut Emu, addr: u16) -> u8 {
     let val = emu.mem.load_u8(addr);
+    emu.mem.store_u8(addr, val);
     let c = emu.cpu.sp.contains(Flags::C) as u8;
     emu.cpu.carry_flag(val & 0x80 != 0);
     emu.mem.store_u8(addr, (val << 1) | c);
     emu.cpu.nz_flags((val << 1) | c);
+    (val << 1) | c
 }
 #[inline]
-pub fn  ror(emu: &mut Emu, addr: u16) {
+pub fn  ror(emu: &mut Emu, addr: u16) -> u8 {
     let val = emu.mem.load_u8(addr);
+    emu.mem.store_u8(addr, val);
     let c = emu.cpu.sp.contains(Flags::C) as u8;
     emu.cpu.carry_flag(val & 0x01 != 0);
     emu.mem.store_u8(addr, (val >> 1) | (c << 7));
     emu.cpu.nz_flags((val >> 1) | (c << 7));
+    (val >> 1) | (c << 7)
 }
 #[inline]
 pub fn  sbc(emu: &mut Emu, val: u8) {
@@ -485,447 +532,704 @@ pub fn sed_i(emu: &mut Emu) {
     emu.cpu.pc += 1;
 }
 pub fn lda_imm(emu: &mut Emu) { 
-    lda(emu, emu.mem.load_u8(addressing::immediate(emu))); 
+    let addr = addressing::immediate(emu);
+    let val = emu.mem.load_u8(addr);
+    lda(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn lda_zpg(emu: &mut Emu) { 
-    lda(emu, emu.mem.load_u8(addressing::zeropage(emu))); 
+    let addr = addressing::zeropage(emu);
+    let val = emu.mem.load_u8(addr);
+    lda(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn lda_zpx(emu: &mut Emu) { 
-    lda(emu, emu.mem.load_u8(addressing::zeropage_x(emu))); 
+    let addr = addressing::zeropage_x(emu);
+    let val = emu.mem.load_u8(addr);
+    lda(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn lda_abs(emu: &mut Emu) { 
-    lda(emu, emu.mem.load_u8(addressing::absolute(emu))); 
+    let addr = addressing::absolute(emu);
+    let val = emu.mem.load_u8(addr);
+    lda(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn lda_abx(emu: &mut Emu) { 
-    lda(emu, emu.mem.load_u8(addressing::absolute_x(emu))); 
+    let addr = addressing::absolute_x(emu);
+    let val = emu.mem.load_u8(addr);
+    lda(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn lda_aby(emu: &mut Emu) { 
-    lda(emu, emu.mem.load_u8(addressing::absolute_y(emu))); 
+    let addr = addressing::absolute_y(emu);
+    let val = emu.mem.load_u8(addr);
+    lda(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn lda_inx(emu: &mut Emu) { 
-    lda(emu, emu.mem.load_u8(addressing::indirect_x(emu))); 
+    let addr = addressing::indirect_x(emu);
+    let val = emu.mem.load_u8(addr);
+    lda(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn lda_iny(emu: &mut Emu) { 
-    lda(emu, emu.mem.load_u8(addressing::indirect_y(emu))); 
+    let addr = addressing::indirect_y(emu);
+    let val = emu.mem.load_u8(addr);
+    lda(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn ldx_imm(emu: &mut Emu) { 
-    ldx(emu, emu.mem.load_u8(addressing::immediate(emu))); 
+    let addr = addressing::immediate(emu);
+    let val = emu.mem.load_u8(addr);
+    ldx(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn ldx_zpg(emu: &mut Emu) { 
-    ldx(emu, emu.mem.load_u8(addressing::zeropage(emu))); 
+    let addr = addressing::zeropage(emu);
+    let val = emu.mem.load_u8(addr);
+    ldx(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn ldx_zpy(emu: &mut Emu) { 
-    ldx(emu, emu.mem.load_u8(addressing::zeropage_y(emu))); 
+    let addr = addressing::zeropage_y(emu);
+    let val = emu.mem.load_u8(addr);
+    ldx(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn ldx_abs(emu: &mut Emu) { 
-    ldx(emu, emu.mem.load_u8(addressing::absolute(emu))); 
+    let addr = addressing::absolute(emu);
+    let val = emu.mem.load_u8(addr);
+    ldx(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn ldx_aby(emu: &mut Emu) { 
-    ldx(emu, emu.mem.load_u8(addressing::absolute_y(emu))); 
+    let addr = addressing::absolute_y(emu);
+    let val = emu.mem.load_u8(addr);
+    ldx(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn ldy_imm(emu: &mut Emu) { 
-    ldy(emu, emu.mem.load_u8(addressing::immediate(emu))); 
+    let addr = addressing::immediate(emu);
+    let val = emu.mem.load_u8(addr);
+    ldy(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn ldy_zpg(emu: &mut Emu) { 
-    ldy(emu, emu.mem.load_u8(addressing::zeropage(emu))); 
+    let addr = addressing::zeropage(emu);
+    let val = emu.mem.load_u8(addr);
+    ldy(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn ldy_zpx(emu: &mut Emu) { 
-    ldy(emu, emu.mem.load_u8(addressing::zeropage_x(emu))); 
+    let addr = addressing::zeropage_x(emu);
+    let val = emu.mem.load_u8(addr);
+    ldy(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn ldy_abs(emu: &mut Emu) { 
-    ldy(emu, emu.mem.load_u8(addressing::absolute(emu))); 
+    let addr = addressing::absolute(emu);
+    let val = emu.mem.load_u8(addr);
+    ldy(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn ldy_abx(emu: &mut Emu) { 
-    ldy(emu, emu.mem.load_u8(addressing::absolute_x(emu))); 
+    let addr = addressing::absolute_x(emu);
+    let val = emu.mem.load_u8(addr);
+    ldy(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn sta_zpg(emu: &mut Emu) { 
-    sta(emu, addressing::zeropage(emu)); 
+    let addr = addressing::zeropage(emu);
+    sta(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn sta_zpx(emu: &mut Emu) { 
-    sta(emu, addressing::zeropage_x(emu)); 
+    let addr = addressing::zeropage_x(emu);
+    sta(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn sta_abs(emu: &mut Emu) { 
-    sta(emu, addressing::absolute(emu)); 
+    let addr = addressing::absolute(emu);
+    sta(emu, addr);
     emu.cpu.pc += 3;
 }
 pub fn sta_abx(emu: &mut Emu) { 
-    sta(emu, addressing::absolute_x(emu)); 
+    let addr = addressing::absolute_x(emu);
+    sta(emu, addr);
     emu.cpu.pc += 3;
 }
 pub fn sta_aby(emu: &mut Emu) { 
-    sta(emu, addressing::absolute_y(emu)); 
+    let addr = addressing::absolute_y(emu);
+    sta(emu, addr);
     emu.cpu.pc += 3;
 }
 pub fn sta_inx(emu: &mut Emu) { 
-    sta(emu, addressing::indirect_x(emu)); 
+    let addr = addressing::indirect_x(emu);
+    sta(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn sta_iny(emu: &mut Emu) { 
-    sta(emu, addressing::indirect_y(emu)); 
+    let addr = addressing::indirect_y(emu);
+    sta(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn stx_zpg(emu: &mut Emu) { 
-    stx(emu, addressing::zeropage(emu)); 
+    let addr = addressing::zeropage(emu);
+    stx(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn stx_zpy(emu: &mut Emu) { 
-    stx(emu, addressing::zeropage_y(emu)); 
+    let addr = addressing::zeropage_y(emu);
+    stx(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn stx_abs(emu: &mut Emu) { 
-    stx(emu, addressing::absolute(emu)); 
+    let addr = addressing::absolute(emu);
+    stx(emu, addr);
     emu.cpu.pc += 3;
 }
 pub fn sty_zpg(emu: &mut Emu) { 
-    sty(emu, addressing::zeropage(emu)); 
+    let addr = addressing::zeropage(emu);
+    sty(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn sty_zpx(emu: &mut Emu) { 
-    sty(emu, addressing::zeropage_x(emu)); 
+    let addr = addressing::zeropage_x(emu);
+    sty(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn sty_abs(emu: &mut Emu) { 
-    sty(emu, addressing::absolute(emu)); 
+    let addr = addressing::absolute(emu);
+    sty(emu, addr);
     emu.cpu.pc += 3;
 }
 pub fn adc_imm(emu: &mut Emu) { 
-    adc(emu, emu.mem.load_u8(addressing::immediate(emu))); 
+    let addr = addressing::immediate(emu);
+    let val = emu.mem.load_u8(addr);
+    adc(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn adc_zpg(emu: &mut Emu) { 
-    adc(emu, emu.mem.load_u8(addressing::zeropage(emu))); 
+    let addr = addressing::zeropage(emu);
+    let val = emu.mem.load_u8(addr);
+    adc(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn adc_zpx(emu: &mut Emu) { 
-    adc(emu, emu.mem.load_u8(addressing::zeropage_x(emu))); 
+    let addr = addressing::zeropage_x(emu);
+    let val = emu.mem.load_u8(addr);
+    adc(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn adc_abs(emu: &mut Emu) { 
-    adc(emu, emu.mem.load_u8(addressing::absolute(emu))); 
+    let addr = addressing::absolute(emu);
+    let val = emu.mem.load_u8(addr);
+    adc(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn adc_abx(emu: &mut Emu) { 
-    adc(emu, emu.mem.load_u8(addressing::absolute_x(emu))); 
+    let addr = addressing::absolute_x(emu);
+    let val = emu.mem.load_u8(addr);
+    adc(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn adc_aby(emu: &mut Emu) { 
-    adc(emu, emu.mem.load_u8(addressing::absolute_y(emu))); 
+    let addr = addressing::absolute_y(emu);
+    let val = emu.mem.load_u8(addr);
+    adc(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn adc_inx(emu: &mut Emu) { 
-    adc(emu, emu.mem.load_u8(addressing::indirect_x(emu))); 
+    let addr = addressing::indirect_x(emu);
+    let val = emu.mem.load_u8(addr);
+    adc(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn adc_iny(emu: &mut Emu) { 
-    adc(emu, emu.mem.load_u8(addressing::indirect_y(emu))); 
+    let addr = addressing::indirect_y(emu);
+    let val = emu.mem.load_u8(addr);
+    adc(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn and_imm(emu: &mut Emu) { 
-    and(emu, emu.mem.load_u8(addressing::immediate(emu))); 
+    let addr = addressing::immediate(emu);
+    let val = emu.mem.load_u8(addr);
+    and(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn and_zpg(emu: &mut Emu) { 
-    and(emu, emu.mem.load_u8(addressing::zeropage(emu))); 
+    let addr = addressing::zeropage(emu);
+    let val = emu.mem.load_u8(addr);
+    and(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn and_zpx(emu: &mut Emu) { 
-    and(emu, emu.mem.load_u8(addressing::zeropage_x(emu))); 
+    let addr = addressing::zeropage_x(emu);
+    let val = emu.mem.load_u8(addr);
+    and(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn and_abs(emu: &mut Emu) { 
-    and(emu, emu.mem.load_u8(addressing::absolute(emu))); 
+    let addr = addressing::absolute(emu);
+    let val = emu.mem.load_u8(addr);
+    and(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn and_abx(emu: &mut Emu) { 
-    and(emu, emu.mem.load_u8(addressing::absolute_x(emu))); 
+    let addr = addressing::absolute_x(emu);
+    let val = emu.mem.load_u8(addr);
+    and(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn and_aby(emu: &mut Emu) { 
-    and(emu, emu.mem.load_u8(addressing::absolute_y(emu))); 
+    let addr = addressing::absolute_y(emu);
+    let val = emu.mem.load_u8(addr);
+    and(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn and_inx(emu: &mut Emu) { 
-    and(emu, emu.mem.load_u8(addressing::indirect_x(emu))); 
+    let addr = addressing::indirect_x(emu);
+    let val = emu.mem.load_u8(addr);
+    and(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn and_iny(emu: &mut Emu) { 
-    and(emu, emu.mem.load_u8(addressing::indirect_y(emu))); 
+    let addr = addressing::indirect_y(emu);
+    let val = emu.mem.load_u8(addr);
+    and(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn asl_zpg(emu: &mut Emu) { 
-    asl(emu, addressing::zeropage(emu)); 
+    let addr = addressing::zeropage(emu);
+    asl(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn asl_zpx(emu: &mut Emu) { 
-    asl(emu, addressing::zeropage_x(emu)); 
+    let addr = addressing::zeropage_x(emu);
+    asl(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn asl_abs(emu: &mut Emu) { 
-    asl(emu, addressing::absolute(emu)); 
+    let addr = addressing::absolute(emu);
+    asl(emu, addr);
     emu.cpu.pc += 3;
 }
 pub fn asl_abx(emu: &mut Emu) { 
-    asl(emu, addressing::absolute_x(emu)); 
+    let addr = addressing::absolute_x(emu);
+    asl(emu, addr);
     emu.cpu.pc += 3;
 }
 pub fn bit_zpg(emu: &mut Emu) { 
-    bit(emu, emu.mem.load_u8(addressing::zeropage(emu))); 
+    let addr = addressing::zeropage(emu);
+    let val = emu.mem.load_u8(addr);
+    bit(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn bit_abs(emu: &mut Emu) { 
-    bit(emu, emu.mem.load_u8(addressing::absolute(emu))); 
+    let addr = addressing::absolute(emu);
+    let val = emu.mem.load_u8(addr);
+    bit(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn cmp_imm(emu: &mut Emu) { 
-    cmp(emu, emu.mem.load_u8(addressing::immediate(emu))); 
+    let addr = addressing::immediate(emu);
+    let val = emu.mem.load_u8(addr);
+    cmp(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn cmp_zpg(emu: &mut Emu) { 
-    cmp(emu, emu.mem.load_u8(addressing::zeropage(emu))); 
+    let addr = addressing::zeropage(emu);
+    let val = emu.mem.load_u8(addr);
+    cmp(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn cmp_zpx(emu: &mut Emu) { 
-    cmp(emu, emu.mem.load_u8(addressing::zeropage_x(emu))); 
+    let addr = addressing::zeropage_x(emu);
+    let val = emu.mem.load_u8(addr);
+    cmp(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn cmp_abs(emu: &mut Emu) { 
-    cmp(emu, emu.mem.load_u8(addressing::absolute(emu))); 
+    let addr = addressing::absolute(emu);
+    let val = emu.mem.load_u8(addr);
+    cmp(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn cmp_abx(emu: &mut Emu) { 
-    cmp(emu, emu.mem.load_u8(addressing::absolute_x(emu))); 
+    let addr = addressing::absolute_x(emu);
+    let val = emu.mem.load_u8(addr);
+    cmp(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn cmp_aby(emu: &mut Emu) { 
-    cmp(emu, emu.mem.load_u8(addressing::absolute_y(emu))); 
+    let addr = addressing::absolute_y(emu);
+    let val = emu.mem.load_u8(addr);
+    cmp(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn cmp_inx(emu: &mut Emu) { 
-    cmp(emu, emu.mem.load_u8(addressing::indirect_x(emu))); 
+    let addr = addressing::indirect_x(emu);
+    let val = emu.mem.load_u8(addr);
+    cmp(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn cmp_iny(emu: &mut Emu) { 
-    cmp(emu, emu.mem.load_u8(addressing::indirect_y(emu))); 
+    let addr = addressing::indirect_y(emu);
+    let val = emu.mem.load_u8(addr);
+    cmp(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn cpx_imm(emu: &mut Emu) { 
-    cpx(emu, emu.mem.load_u8(addressing::immediate(emu))); 
+    let addr = addressing::immediate(emu);
+    let val = emu.mem.load_u8(addr);
+    cpx(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn cpx_zpg(emu: &mut Emu) { 
-    cpx(emu, emu.mem.load_u8(addressing::zeropage(emu))); 
+    let addr = addressing::zeropage(emu);
+    let val = emu.mem.load_u8(addr);
+    cpx(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn cpx_abs(emu: &mut Emu) { 
-    cpx(emu, emu.mem.load_u8(addressing::absolute(emu))); 
+    let addr = addressing::absolute(emu);
+    let val = emu.mem.load_u8(addr);
+    cpx(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn cpy_imm(emu: &mut Emu) { 
-    cpy(emu, emu.mem.load_u8(addressing::immediate(emu))); 
+    let addr = addressing::immediate(emu);
+    let val = emu.mem.load_u8(addr);
+    cpy(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn cpy_zpg(emu: &mut Emu) { 
-    cpy(emu, emu.mem.load_u8(addressing::zeropage(emu))); 
+    let addr = addressing::zeropage(emu);
+    let val = emu.mem.load_u8(addr);
+    cpy(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn cpy_abs(emu: &mut Emu) { 
-    cpy(emu, emu.mem.load_u8(addressing::absolute(emu))); 
+    let addr = addressing::absolute(emu);
+    let val = emu.mem.load_u8(addr);
+    cpy(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn dec_zpg(emu: &mut Emu) { 
-    dec(emu, addressing::zeropage(emu)); 
+    let addr = addressing::zeropage(emu);
+    dec(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn dec_zpx(emu: &mut Emu) { 
-    dec(emu, addressing::zeropage_x(emu)); 
+    let addr = addressing::zeropage_x(emu);
+    dec(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn dec_abs(emu: &mut Emu) { 
-    dec(emu, addressing::absolute(emu)); 
+    let addr = addressing::absolute(emu);
+    dec(emu, addr);
     emu.cpu.pc += 3;
 }
 pub fn dec_abx(emu: &mut Emu) { 
-    dec(emu, addressing::absolute_x(emu)); 
+    let addr = addressing::absolute_x(emu);
+    dec(emu, addr);
     emu.cpu.pc += 3;
 }
 pub fn eor_imm(emu: &mut Emu) { 
-    eor(emu, emu.mem.load_u8(addressing::immediate(emu))); 
+    let addr = addressing::immediate(emu);
+    let val = emu.mem.load_u8(addr);
+    eor(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn eor_zpg(emu: &mut Emu) { 
-    eor(emu, emu.mem.load_u8(addressing::zeropage(emu))); 
+    let addr = addressing::zeropage(emu);
+    let val = emu.mem.load_u8(addr);
+    eor(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn eor_zpx(emu: &mut Emu) { 
-    eor(emu, emu.mem.load_u8(addressing::zeropage_x(emu))); 
+    let addr = addressing::zeropage_x(emu);
+    let val = emu.mem.load_u8(addr);
+    eor(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn eor_abs(emu: &mut Emu) { 
-    eor(emu, emu.mem.load_u8(addressing::absolute(emu))); 
+    let addr = addressing::absolute(emu);
+    let val = emu.mem.load_u8(addr);
+    eor(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn eor_abx(emu: &mut Emu) { 
-    eor(emu, emu.mem.load_u8(addressing::absolute_x(emu))); 
+    let addr = addressing::absolute_x(emu);
+    let val = emu.mem.load_u8(addr);
+    eor(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn eor_aby(emu: &mut Emu) { 
-    eor(emu, emu.mem.load_u8(addressing::absolute_y(emu))); 
+    let addr = addressing::absolute_y(emu);
+    let val = emu.mem.load_u8(addr);
+    eor(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn eor_inx(emu: &mut Emu) { 
-    eor(emu, emu.mem.load_u8(addressing::indirect_x(emu))); 
+    let addr = addressing::indirect_x(emu);
+    let val = emu.mem.load_u8(addr);
+    eor(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn eor_iny(emu: &mut Emu) { 
-    eor(emu, emu.mem.load_u8(addressing::indirect_y(emu))); 
+    let addr = addressing::indirect_y(emu);
+    let val = emu.mem.load_u8(addr);
+    eor(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn inc_zpg(emu: &mut Emu) { 
-    inc(emu, addressing::zeropage(emu)); 
+    let addr = addressing::zeropage(emu);
+    inc(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn inc_zpx(emu: &mut Emu) { 
-    inc(emu, addressing::zeropage_x(emu)); 
+    let addr = addressing::zeropage_x(emu);
+    inc(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn inc_abs(emu: &mut Emu) { 
-    inc(emu, addressing::absolute(emu)); 
+    let addr = addressing::absolute(emu);
+    inc(emu, addr);
     emu.cpu.pc += 3;
 }
 pub fn inc_abx(emu: &mut Emu) { 
-    inc(emu, addressing::absolute_x(emu)); 
+    let addr = addressing::absolute_x(emu);
+    inc(emu, addr);
     emu.cpu.pc += 3;
 }
 pub fn lsr_zpg(emu: &mut Emu) { 
-    lsr(emu, addressing::zeropage(emu)); 
+    let addr = addressing::zeropage(emu);
+    lsr(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn lsr_zpx(emu: &mut Emu) { 
-    lsr(emu, addressing::zeropage_x(emu)); 
+    let addr = addressing::zeropage_x(emu);
+    lsr(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn lsr_abs(emu: &mut Emu) { 
-    lsr(emu, addressing::absolute(emu)); 
+    let addr = addressing::absolute(emu);
+    lsr(emu, addr);
     emu.cpu.pc += 3;
 }
 pub fn lsr_abx(emu: &mut Emu) { 
-    lsr(emu, addressing::absolute_x(emu)); 
+    let addr = addressing::absolute_x(emu);
+    lsr(emu, addr);
     emu.cpu.pc += 3;
 }
 pub fn ora_imm(emu: &mut Emu) { 
-    ora(emu, emu.mem.load_u8(addressing::immediate(emu))); 
+    let addr = addressing::immediate(emu);
+    let val = emu.mem.load_u8(addr);
+    ora(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn ora_zpg(emu: &mut Emu) { 
-    ora(emu, emu.mem.load_u8(addressing::zeropage(emu))); 
+    let addr = addressing::zeropage(emu);
+    let val = emu.mem.load_u8(addr);
+    ora(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn ora_zpx(emu: &mut Emu) { 
-    ora(emu, emu.mem.load_u8(addressing::zeropage_x(emu))); 
+    let addr = addressing::zeropage_x(emu);
+    let val = emu.mem.load_u8(addr);
+    ora(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn ora_abs(emu: &mut Emu) { 
-    ora(emu, emu.mem.load_u8(addressing::absolute(emu))); 
+    let addr = addressing::absolute(emu);
+    let val = emu.mem.load_u8(addr);
+    ora(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn ora_abx(emu: &mut Emu) { 
-    ora(emu, emu.mem.load_u8(addressing::absolute_x(emu))); 
+    let addr = addressing::absolute_x(emu);
+    let val = emu.mem.load_u8(addr);
+    ora(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn ora_aby(emu: &mut Emu) { 
-    ora(emu, emu.mem.load_u8(addressing::absolute_y(emu))); 
+    let addr = addressing::absolute_y(emu);
+    let val = emu.mem.load_u8(addr);
+    ora(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn ora_inx(emu: &mut Emu) { 
-    ora(emu, emu.mem.load_u8(addressing::indirect_x(emu))); 
+    let addr = addressing::indirect_x(emu);
+    let val = emu.mem.load_u8(addr);
+    ora(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn ora_iny(emu: &mut Emu) { 
-    ora(emu, emu.mem.load_u8(addressing::indirect_y(emu))); 
+    let addr = addressing::indirect_y(emu);
+    let val = emu.mem.load_u8(addr);
+    ora(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn rol_zpg(emu: &mut Emu) { 
-    rol(emu, addressing::zeropage(emu)); 
+    let addr = addressing::zeropage(emu);
+    rol(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn rol_zpx(emu: &mut Emu) { 
-    rol(emu, addressing::zeropage_x(emu)); 
+    let addr = addressing::zeropage_x(emu);
+    rol(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn rol_abs(emu: &mut Emu) { 
-    rol(emu, addressing::absolute(emu)); 
+    let addr = addressing::absolute(emu);
+    rol(emu, addr);
     emu.cpu.pc += 3;
 }
 pub fn rol_abx(emu: &mut Emu) { 
-    rol(emu, addressing::absolute_x(emu)); 
+    let addr = addressing::absolute_x(emu);
+    rol(emu, addr);
     emu.cpu.pc += 3;
 }
 pub fn ror_zpg(emu: &mut Emu) { 
-    ror(emu, addressing::zeropage(emu)); 
+    let addr = addressing::zeropage(emu);
+    ror(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn ror_zpx(emu: &mut Emu) { 
-    ror(emu, addressing::zeropage_x(emu)); 
+    let addr = addressing::zeropage_x(emu);
+    ror(emu, addr);
     emu.cpu.pc += 2;
 }
 pub fn ror_abs(emu: &mut Emu) { 
-    ror(emu, addressing::absolute(emu)); 
+    let addr = addressing::absolute(emu);
+    ror(emu, addr);
     emu.cpu.pc += 3;
 }
 pub fn ror_abx(emu: &mut Emu) { 
-    ror(emu, addressing::absolute_x(emu)); 
+    let addr = addressing::absolute_x(emu);
+    ror(emu, addr);
     emu.cpu.pc += 3;
 }
 pub fn sbc_imm(emu: &mut Emu) { 
-    sbc(emu, emu.mem.load_u8(addressing::immediate(emu))); 
+    let addr = addressing::immediate(emu);
+    let val = emu.mem.load_u8(addr);
+    sbc(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn sbc_zpg(emu: &mut Emu) { 
-    sbc(emu, emu.mem.load_u8(addressing::zeropage(emu))); 
+    let addr = addressing::zeropage(emu);
+    let val = emu.mem.load_u8(addr);
+    sbc(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn sbc_zpx(emu: &mut Emu) { 
-    sbc(emu, emu.mem.load_u8(addressing::zeropage_x(emu))); 
+    let addr = addressing::zeropage_x(emu);
+    let val = emu.mem.load_u8(addr);
+    sbc(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn sbc_abs(emu: &mut Emu) { 
-    sbc(emu, emu.mem.load_u8(addressing::absolute(emu))); 
+    let addr = addressing::absolute(emu);
+    let val = emu.mem.load_u8(addr);
+    sbc(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn sbc_abx(emu: &mut Emu) { 
-    sbc(emu, emu.mem.load_u8(addressing::absolute_x(emu))); 
+    let addr = addressing::absolute_x(emu);
+    let val = emu.mem.load_u8(addr);
+    sbc(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn sbc_aby(emu: &mut Emu) { 
-    sbc(emu, emu.mem.load_u8(addressing::absolute_y(emu))); 
+    let addr = addressing::absolute_y(emu);
+    let val = emu.mem.load_u8(addr);
+    sbc(emu, val);
     emu.cpu.pc += 3;
 }
 pub fn sbc_inx(emu: &mut Emu) { 
-    sbc(emu, emu.mem.load_u8(addressing::indirect_x(emu))); 
+    let addr = addressing::indirect_x(emu);
+    let val = emu.mem.load_u8(addr);
+    sbc(emu, val);
     emu.cpu.pc += 2;
 }
 pub fn sbc_iny(emu: &mut Emu) { 
-    sbc(emu, emu.mem.load_u8(addressing::indirect_y(emu))); 
+    let addr = addressing::indirect_y(emu);
+    let val = emu.mem.load_u8(addr);
+    sbc(emu, val);
     emu.cpu.pc += 2;
 }
 
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::Flags;
+    use crate::emu::Emu;
+    use crate::fixture::run;
+
+    #[test]
+    fn adc_flags() {
+        // lda #$50, adc #$50: signed overflow, no carry
+        let mut emu = Emu::default();
+        assert_eq!(run(&mut emu, &[0xa9, 0x50, 0x69, 0x50], 2), 4);
+        assert_eq!(emu.cpu.a, 0xa0);
+        assert!(emu.cpu.sp.contains(Flags::V | Flags::N));
+        assert!(!emu.cpu.sp.intersects(Flags::C | Flags::Z));
+
+        // lda #$ff, adc #$01: carry and zero
+        let mut emu = Emu::default();
+        run(&mut emu, &[0xa9, 0xff, 0x69, 0x01], 2);
+        assert_eq!(emu.cpu.a, 0x00);
+        assert!(emu.cpu.sp.contains(Flags::C | Flags::Z));
+        assert!(!emu.cpu.sp.contains(Flags::V));
+    }
+
+    #[test]
+    fn page_cross_penalty() {
+        // ldx #$01, lda $0200,x
+        let mut emu = Emu::default();
+        assert_eq!(run(&mut emu, &[0xa2, 0x01, 0xbd, 0x00, 0x02], 2), 6);
+        // ldx #$01, lda $02ff,x
+        let mut emu = Emu::default();
+        assert_eq!(run(&mut emu, &[0xa2, 0x01, 0xbd, 0xff, 0x02], 2), 7);
+        // stores always take the extra cycle: ldx #$01, sta $0200,x / sta $02ff,x
+        let mut emu = Emu::default();
+        assert_eq!(run(&mut emu, &[0xa2, 0x01, 0x9d, 0x00, 0x02, 0x9d, 0xff, 0x02], 3), 12);
+
+        // ldy #$01, lda ($10),y with $10 = $02ff
+        let mut emu = Emu::default();
+        emu.mem.copy_from_slice(0x10, &[0xff, 0x02, 0x00, 0x02]);
+        assert_eq!(run(&mut emu, &[0xa0, 0x01, 0xb1, 0x10], 2), 8);
+        // ldy #$01, lda ($12),y with $12 = $0200
+        let mut emu2 = Emu::default();
+        emu2.mem.copy_from_slice(0x10, &[0xff, 0x02, 0x00, 0x02]);
+        assert_eq!(run(&mut emu2, &[0xa0, 0x01, 0xb1, 0x12], 2), 7);
+    }
+
+    #[test]
+    fn branch_penalty() {
+        // ldx #$01, bne +2: taken, same page
+        let mut emu = Emu::default();
+        assert_eq!(run(&mut emu, &[0xa2, 0x01, 0xd0, 0x02], 2), 5);
+        assert_eq!(emu.cpu.pc, 0x0606);
+        // ldx #$00, bne: not taken
+        let mut emu = Emu::default();
+        assert_eq!(run(&mut emu, &[0xa2, 0x00, 0xd0, 0x02], 2), 4);
+        assert_eq!(emu.cpu.pc, 0x0604);
+        // ldx #$01, bne -$10: taken into the previous page
+        let mut emu = Emu::default();
+        assert_eq!(run(&mut emu, &[0xa2, 0x01, 0xd0, 0xf0], 2), 6);
+        assert_eq!(emu.cpu.pc, 0x05f4);
+    }
+
+    #[test]
+    fn jmp_indirect_wraps_in_page() {
+        let mut emu = Emu::default();
+        emu.mem.copy_from_slice(0x0200, &[0x12]);
+        emu.mem.copy_from_slice(0x02ff, &[0x34, 0x56]);
+        // jmp ($02ff) reads the high byte from $0200, not $0300
+        assert_eq!(run(&mut emu, &[0x6c, 0xff, 0x02], 1), 5);
+        assert_eq!(emu.cpu.pc, 0x1234);
+    }
+}
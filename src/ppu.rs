@@ -1,109 +1,665 @@
 use bitflags::bitflags;
 use crate::cart::*;
-use crate::mem::Mem;
+use crate::frame::Frame;
+use crate::mapper::Mapper;
+use crate::palette::SYSTEM_PALETTE;
 
+// the ppu owns its nametable ram, palette ram and oam.
+// pattern tables and the nametable layout belong to the cartridge, so every
+// access that may reach them takes the mapper as a parameter.
 pub struct Ppu {
-    mem: Box<Mem>,
+    // 2k on the console. four-screen boards add another 2k for the other two
+    // nametables, kept here so every layout indexes the same array.
+    vram: [u8; 0x1000],
     palette: [u8; 32],
-    addr: PpuAddressRegister,
+    oam: [u8; 0x100],
+    loopy: LoopyRegister,
     pub ctrl: ControlRegister,
+    pub mask: MaskRegister,
+    pub status: StatusRegister,
+    oam_addr: u8,
     internal_data_buf: u8,
+    // the ppu data bus keeps the last value written to any register.
+    // reads from write-only registers and the low bits of $2002 return it.
+    io_latch: u8,
+    pub frame: Frame,
+    // dot 0-340 and scanline 0-261. 261 is the pre-render line.
+    pub cycle: u16,
+    pub scanline: u16,
+    odd_frame: bool,
+    // set when vblank starts. the frontend clears it after taking the frame.
+    pub frame_complete: bool,
+    // a $2002 read just before vblank starts suppresses the flag and the nmi
+    suppress_vblank: bool,
+    nmi_edge: bool,
+    bg: BackgroundPipeline,
+    sprites: SpriteLine,
+}
+
+const SCANLINES_PER_FRAME: u16 = 262;
+const DOTS_PER_SCANLINE: u16 = 341;
+const VBLANK_SCANLINE: u16 = 241;
+const PRE_RENDER_SCANLINE: u16 = 261;
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Ppu::new()
+    }
 }
 
 impl Ppu {
-    pub fn new(mem: Box<Mem>) -> Self {
+    pub fn new() -> Self {
         Ppu {
-            mem: mem,
+            vram: [0; 0x1000],
             palette: [0; 32],
-            addr: PpuAddressRegister::new(),
+            oam: [0; 0x100],
+            loopy: LoopyRegister::new(),
             ctrl: ControlRegister::new(),
+            mask: MaskRegister::new(),
+            status: StatusRegister::new(),
+            oam_addr: 0,
             internal_data_buf: 0,
+            io_latch: 0,
+            frame: Frame::new(),
+            cycle: 0,
+            scanline: 0,
+            odd_frame: false,
+            frame_complete: false,
+            suppress_vblank: false,
+            nmi_edge: false,
+            bg: BackgroundPipeline::new(),
+            sprites: SpriteLine::new(),
+        }
+    }
+
+    // cpu side of $2000-$2007. addr is already mirrored down.
+    pub fn write_register(&mut self, addr: u16, data: u8, cart: &mut dyn Mapper) {
+        self.io_latch = data;
+        match addr {
+            0x2000 => self.write_to_control_register(data),
+            0x2001 => self.mask.update(data),
+            0x2003 => self.oam_addr = data,
+            0x2004 => self.write_oam_data(data),
+            0x2005 => self.write_to_scroll_register(data),
+            0x2006 => self.write_to_address_register(data, cart),
+            0x2007 => self.write_data(data, cart),
+            _ => {}
+        }
+    }
+
+    pub fn read_register(&mut self, addr: u16, cart: &mut dyn Mapper) -> u8 {
+        let r = match addr {
+            0x2002 => self.read_status(),
+            0x2004 => self.oam[self.oam_addr as usize],
+            0x2007 => self.read_data(cart),
+            _ => self.io_latch,
+        };
+        self.io_latch = r;
+        r
+    }
+
+    // advance one dot
+    pub fn tick(&mut self, cart: &mut dyn Mapper) {
+        let render_line = self.scanline < Frame::HEIGHT as u16 || self.scanline == PRE_RENDER_SCANLINE;
+        if render_line && self.mask.rendering_enabled() {
+            self.render_tick(cart);
+        }
+        if self.scanline < Frame::HEIGHT as u16 && (1..=256).contains(&self.cycle) {
+            self.output_pixel();
+        }
+        match (self.scanline, self.cycle) {
+            (VBLANK_SCANLINE, 1) => {
+                if !self.suppress_vblank {
+                    self.status.insert(StatusRegister::VBLANK_STARTED);
+                    if self.ctrl.contains(ControlRegister::GENERATE_NMI) {
+                        self.nmi_edge = true;
+                    }
+                }
+                self.suppress_vblank = false;
+                self.frame_complete = true;
+            }
+            (PRE_RENDER_SCANLINE, 1) => {
+                self.status.remove(StatusRegister::VBLANK_STARTED | StatusRegister::SPRITE_ZERO_HIT | StatusRegister::SPRITE_OVERFLOW);
+            }
+            _ => {}
+        }
+
+        self.cycle += 1;
+        // odd frames skip the last dot of the pre-render line while rendering is on
+        if self.scanline == PRE_RENDER_SCANLINE && self.cycle == DOTS_PER_SCANLINE - 1
+            && self.odd_frame && self.mask.rendering_enabled() {
+            self.cycle = DOTS_PER_SCANLINE;
+        }
+        if self.cycle == DOTS_PER_SCANLINE {
+            self.cycle = 0;
+            self.scanline += 1;
+            if self.scanline == SCANLINES_PER_FRAME {
+                self.scanline = 0;
+                self.odd_frame = !self.odd_frame;
+            }
+        }
+    }
+
+    pub fn nmi_output(&self) -> bool {
+        self.status.contains(StatusRegister::VBLANK_STARTED) && self.ctrl.contains(ControlRegister::GENERATE_NMI)
+    }
+
+    // true once for every rising edge of the nmi output
+    pub fn take_nmi(&mut self) -> bool {
+        std::mem::replace(&mut self.nmi_edge, false)
+    }
+
+    fn read_status(&mut self) -> u8 {
+        if self.scanline == VBLANK_SCANLINE && self.cycle == 1 {
+            self.suppress_vblank = true;
         }
+        let r = self.status.bits() | (self.io_latch & 0b0001_1111);
+        self.status.remove(StatusRegister::VBLANK_STARTED);
+        self.loopy.w = false;
+        r
     }
 
-    fn write_to_address_register(&mut self, data: u8) {
-        self.addr.update(data);
+    fn write_oam_data(&mut self, data: u8) {
+        self.oam[self.oam_addr as usize] = data;
+        self.oam_addr = self.oam_addr.wrapping_add(1);
+    }
+
+    fn write_to_scroll_register(&mut self, data: u8) {
+        self.loopy.write_scroll(data);
+    }
+
+    fn write_to_address_register(&mut self, data: u8, cart: &mut dyn Mapper) {
+        self.loopy.write_addr(data);
+        // outside rendering v drives the address bus, which boards like mmc3 watch
+        if !self.loopy.w {
+            cart.ppu_addr(self.loopy.v & 0x3fff);
+        }
     }
 
     pub fn write_to_control_register(&mut self, val: u8) {
+        let before = self.nmi_output();
         self.ctrl.update(val);
+        self.loopy.write_ctrl(val);
+        // enabling nmi during vblank fires it immediately
+        if !before && self.nmi_output() {
+            self.nmi_edge = true;
+        }
     }
     fn increment_vram_addr(&mut self) {
-        self.addr.increment(self.ctrl.vram_addr_increment());
+        let render_line = self.scanline < Frame::HEIGHT as u16 || self.scanline == PRE_RENDER_SCANLINE;
+        if render_line && self.mask.rendering_enabled() {
+            // $2007 access while rendering bumps both coarse x and y
+            self.loopy.increment_x();
+            self.loopy.increment_y();
+        } else {
+            self.loopy.v = (self.loopy.v + self.ctrl.vram_addr_increment() as u16) & 0x7fff;
+        }
     }
 
-    fn read_data(&mut self) -> u8 {
-        let addr = self.addr.get();
+    fn read_data(&mut self, cart: &mut dyn Mapper) -> u8 {
+        let addr = self.loopy.v & 0x3fff;
         self.increment_vram_addr();
 
         match addr {
-            0..=0x1fff => {
-                let result = self.internal_data_buf;
-                self.internal_data_buf = self.mem.rom.chr_rom[addr as usize];
-                result
+            0x3f00..=0x3fff => {
+                // palette reads are not buffered, but the buffer is filled
+                // with the nametable byte "underneath" the palette.
+                self.internal_data_buf = self.mem_read(addr - 0x1000, cart);
+                self.palette[palette_index(addr)]
             }
-            0x2000..=0x2fff => {
+            _ => {
                 let result = self.internal_data_buf;
-                self.internal_data_buf = self.mem.vram[self.mirror_vram_addr(addr) as usize];
+                self.internal_data_buf = self.mem_read(addr, cart);
                 result
             }
-            0x3000..=0x3eff => panic!(
-                "addr space 0x3000..0x3eff is not expected to be used, requested = {} ",
-                addr
-            ),
-            0x3f00..=0x3fff => self.palette[(addr - 0x3f00) as usize],
-            _ => panic!("unexpected access to mirrored space {}", addr),
         }
     }
 
-    pub fn mirror_vram_addr(&self, addr: u16) -> u16 {
+    fn write_data(&mut self, data: u8, cart: &mut dyn Mapper) {
+        let addr = self.loopy.v & 0x3fff;
+        self.increment_vram_addr();
+        self.mem_write(addr, data, cart);
+    }
+
+    // ppu address space $0000-$3fff
+    fn mem_read(&self, addr: u16, cart: &mut dyn Mapper) -> u8 {
+        cart.ppu_addr(addr & 0x3fff);
+        match addr & 0x3fff {
+            0..=0x1fff => cart.ppu_read(addr),
+            0x2000..=0x3eff => self.vram[self.mirror_vram_addr(addr, cart.mirroring()) as usize],
+            addr => self.palette[palette_index(addr)],
+        }
+    }
+
+    fn mem_write(&mut self, addr: u16, data: u8, cart: &mut dyn Mapper) {
+        cart.ppu_addr(addr & 0x3fff);
+        match addr & 0x3fff {
+            0..=0x1fff => cart.ppu_write(addr, data),
+            0x2000..=0x3eff => {
+                let i = self.mirror_vram_addr(addr, cart.mirroring()) as usize;
+                self.vram[i] = data;
+            }
+            addr => self.palette[palette_index(addr)] = data,
+        }
+    }
+
+    pub fn mirror_vram_addr(&self, addr: u16, mirroring: Mirroring) -> u16 {
         let mirrored_vram = addr & 0b10111111111111; // mirror down 0x3000-0x3eff to 0x2000 - 0x2eff
         let vram_index = mirrored_vram - 0x2000; // to vram vector
         let name_table = vram_index / 0x400; // to the name table index
-        match (&self.mem.rom.screen_mirroring, name_table) {
+        match (mirroring, name_table) {
             (Mirroring::VERTICAL, 2) | (Mirroring::VERTICAL, 3) => vram_index - 0x800,
             (Mirroring::HORIZONTAL, 2) => vram_index - 0x400,
             (Mirroring::HORIZONTAL, 1) => vram_index - 0x400,
             (Mirroring::HORIZONTAL, 3) => vram_index - 0x800,
+            (Mirroring::SINGLE_SCREEN_LOWER, _) => vram_index & 0x3ff,
+            (Mirroring::SINGLE_SCREEN_UPPER, _) => 0x400 | (vram_index & 0x3ff),
             _ => vram_index,
         }
     }
 }
 
-pub struct PpuAddressRegister {
-    pub value: u16,
-    hi_ptr: bool,
+// rendering
+impl Ppu {
+    // background fetches, loopy increments and sprite fetches for one dot.
+    // only called on the visible and pre-render lines while rendering is enabled.
+    fn render_tick(&mut self, cart: &mut dyn Mapper) {
+        let cycle = self.cycle;
+        if (2..=257).contains(&cycle) || (322..=337).contains(&cycle) {
+            self.bg.shift();
+        }
+        if (1..=256).contains(&cycle) || (321..=336).contains(&cycle) {
+            match (cycle - 1) % 8 {
+                0 => {
+                    self.bg.reload();
+                    self.bg.next_tile = self.mem_read(0x2000 | (self.loopy.v & 0x0fff), cart);
+                }
+                2 => {
+                    let v = self.loopy.v;
+                    let attr = self.mem_read(0x23c0 | (v & 0x0c00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07), cart);
+                    let shift = ((v >> 4) & 0x04) | (v & 0x02);
+                    self.bg.next_attr = (attr >> shift) & 0b11;
+                }
+                4 => {
+                    let addr = self.bg_pattern_addr();
+                    self.bg.next_lo = self.mem_read(addr, cart);
+                }
+                6 => {
+                    let addr = self.bg_pattern_addr();
+                    self.bg.next_hi = self.mem_read(addr + 8, cart);
+                }
+                7 => self.loopy.increment_x(),
+                _ => {}
+            }
+        }
+        match cycle {
+            256 => self.loopy.increment_y(),
+            257 => {
+                self.bg.reload();
+                self.loopy.copy_x();
+                self.evaluate_sprites();
+            }
+            258..=320 => {
+                if self.scanline == PRE_RENDER_SCANLINE && (280..=304).contains(&cycle) {
+                    self.loopy.copy_y();
+                }
+                self.fetch_sprites(cycle - 257, cart);
+            }
+            // unused nametable fetches
+            338 | 340 => {
+                self.mem_read(0x2000 | (self.loopy.v & 0x0fff), cart);
+            }
+            _ => {}
+        }
+    }
+
+    #[inline]
+    fn bg_pattern_addr(&self) -> u16 {
+        self.ctrl.bknd_pattern_addr() + self.bg.next_tile as u16 * 16 + self.loopy.fine_y()
+    }
+
+    // find the sprites of the next line. the first 8 in oam order are kept.
+    fn evaluate_sprites(&mut self) {
+        let height = self.ctrl.sprite_size() as u16;
+        self.sprites.count = 0;
+        if self.scanline == PRE_RENDER_SCANLINE {
+            return;
+        }
+        let mut n = 0;
+        while n < 64 && self.sprites.count < 8 {
+            let sprite = &self.oam[n * 4..n * 4 + 4];
+            let row = self.scanline.wrapping_sub(sprite[0] as u16);
+            if row < height {
+                let i = self.sprites.count;
+                self.sprites.index[i] = n as u8;
+                self.sprites.row[i] = row as u8;
+                self.sprites.attr[i] = sprite[2];
+                self.sprites.x[i] = sprite[3];
+                self.sprites.count += 1;
+            }
+            n += 1;
+        }
+
+        // overflow check for the rest of oam. the hardware increments the byte
+        // offset m together with n while the sprite is out of range, so it reads
+        // tile/attribute/x bytes as y coordinates in a diagonal pattern.
+        let mut m = 0;
+        while n < 64 {
+            let y = self.oam[n * 4 + m] as u16;
+            if self.scanline.wrapping_sub(y) < height {
+                self.status.insert(StatusRegister::SPRITE_OVERFLOW);
+                break;
+            }
+            n += 1;
+            m = (m + 1) & 3;
+        }
+    }
+
+    // dots 257-320: 8 dots per sprite slot. two garbage nametable fetches, then the
+    // pattern low byte on the 4th and high byte on the 6th. empty slots fetch tile $ff
+    // like the hardware does.
+    fn fetch_sprites(&mut self, dot: u16, cart: &mut dyn Mapper) {
+        let slot = ((dot - 1) / 8) as usize;
+        let step = (dot - 1) % 8;
+        // the garbage fetches keep a12 low between the pattern fetches, which mmc3's
+        // irq counter sees
+        if step == 0 || step == 2 {
+            self.mem_read(0x2000 | (self.loopy.v & 0x0fff), cart);
+            return;
+        }
+        if step != 4 && step != 6 {
+            return;
+        }
+        let height = self.ctrl.sprite_size() as u16;
+        let (tile, mut row, attr) = if slot < self.sprites.count {
+            (self.oam[self.sprites.index[slot] as usize * 4 + 1] as u16, self.sprites.row[slot] as u16, self.sprites.attr[slot])
+        } else {
+            (0xff, 0, 0)
+        };
+        if attr & 0b1000_0000 != 0 {
+            row = height - 1 - row;
+        }
+        let addr = if height == 16 {
+            // 8x16 sprites pick the bank with bit 0 of the tile number
+            (tile & 1) * 0x1000 + ((tile & 0xfe) + row / 8) * 16 + row % 8
+        } else {
+            self.ctrl.sprt_pattern_addr() + tile * 16 + row
+        };
+        let mut data = self.mem_read(if step == 4 { addr } else { addr + 8 }, cart);
+        if slot >= self.sprites.count {
+            return;
+        }
+        if attr & 0b0100_0000 != 0 {
+            data = data.reverse_bits();
+        }
+        if step == 4 {
+            self.sprites.lo[slot] = data;
+        } else {
+            self.sprites.hi[slot] = data;
+        }
+    }
+
+    fn output_pixel(&mut self) {
+        let x = (self.cycle - 1) as usize;
+        let y = self.scanline as usize;
+        if !self.mask.rendering_enabled() {
+            // with rendering off the backdrop is shown, or the palette entry v points at
+            let v = self.loopy.v & 0x3fff;
+            let offset = if v >= 0x3f00 { (v & 0x1f) as u8 } else { 0 };
+            let rgb = self.color(offset);
+            self.frame.set_pixel(x, y, rgb);
+            return;
+        }
+
+        let (bg_value, bg_palette) = if self.mask.contains(MaskRegister::SHOW_BACKGROUND)
+            && (x >= 8 || self.mask.contains(MaskRegister::LEFTMOST_8PXL_BACKGROUND)) {
+            self.bg.pixel(self.loopy.x)
+        } else {
+            (0, 0)
+        };
+        let sprite = if self.mask.contains(MaskRegister::SHOW_SPRITES)
+            && (x >= 8 || self.mask.contains(MaskRegister::LEFTMOST_8PXL_SPRITE)) {
+            self.sprites.pixel(x)
+        } else {
+            None
+        };
+
+        // sprite 0 hit: opaque sprite 0 over opaque background, never at x 255
+        if let Some((_, _, 0)) = sprite {
+            if bg_value != 0 && x != 255 {
+                self.status.insert(StatusRegister::SPRITE_ZERO_HIT);
+            }
+        }
+
+        let offset = match sprite {
+            Some((value, attr, _)) if bg_value == 0 || attr & 0b0010_0000 == 0 => {
+                0x10 + (attr & 0b11) * 4 + value
+            }
+            _ if bg_value != 0 => bg_palette * 4 + bg_value,
+            _ => 0,
+        };
+        let rgb = self.color(offset);
+        self.frame.set_pixel(x, y, rgb);
+    }
+
+    fn color(&self, offset: u8) -> (u8, u8, u8) {
+        let mut index = self.palette[palette_index(offset as u16)] & 0x3f;
+        if self.mask.contains(MaskRegister::GREYSCALE) {
+            index &= 0x30;
+        }
+        let (mut r, mut g, mut b) = SYSTEM_PALETTE[index as usize];
+        // emphasis darkens the two other channels
+        let dim = |c: u8| (c as u16 * 3 / 4) as u8;
+        if self.mask.contains(MaskRegister::EMPHASISE_RED) {
+            g = dim(g);
+            b = dim(b);
+        }
+        if self.mask.contains(MaskRegister::EMPHASISE_GREEN) {
+            r = dim(r);
+            b = dim(b);
+        }
+        if self.mask.contains(MaskRegister::EMPHASISE_BLUE) {
+            r = dim(r);
+            g = dim(g);
+        }
+        (r, g, b)
+    }
+}
+
+// $3f10/$3f14/$3f18/$3f1c are mirrors of $3f00/$3f04/$3f08/$3f0c
+fn palette_index(addr: u16) -> usize {
+    let i = (addr & 0x1f) as usize;
+    if i >= 0x10 && i & 0x03 == 0 {
+        i - 0x10
+    } else {
+        i
+    }
 }
 
-impl PpuAddressRegister {
+// internal scroll/address registers, named after loopy's "the skinny on nes scrolling".
+// v and t are laid out as 0yyy NNYY YYYX XXXX:
+// fine y, nametable select, coarse y, coarse x.
+pub struct LoopyRegister {
+    pub v: u16,
+    pub t: u16,
+    // fine x scroll
+    pub x: u8,
+    // first/second write toggle shared by $2005 and $2006
+    pub w: bool,
+}
+
+impl Default for LoopyRegister {
+    fn default() -> Self {
+        LoopyRegister::new()
+    }
+}
+
+impl LoopyRegister {
     pub fn new() -> Self {
-        PpuAddressRegister {
-            value: 0,
-            hi_ptr: true,
+        LoopyRegister {
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
         }
     }
 
-    pub fn update(&mut self, data: u8) {
-        if self.hi_ptr {
-            self.value |= (data as u16) << 8;
+    pub fn write_ctrl(&mut self, data: u8) {
+        self.t = (self.t & !0x0c00) | ((data as u16 & 0b11) << 10);
+    }
+
+    pub fn write_scroll(&mut self, data: u8) {
+        if !self.w {
+            self.t = (self.t & !0x001f) | (data as u16 >> 3);
+            self.x = data & 0b111;
         } else {
-            self.value |= data as u16;
+            self.t = (self.t & !0x73e0) | ((data as u16 & 0b111) << 12) | ((data as u16 >> 3) << 5);
         }
-        self.value &= 0x3fff;
-        self.hi_ptr = !self.hi_ptr;
+        self.w = !self.w;
     }
 
-    pub fn increment(&mut self, inc: u8) {
-        self.value += inc as u16;
-        self.value &= 0x3fff;
+    pub fn write_addr(&mut self, data: u8) {
+        if !self.w {
+            self.t = (self.t & 0x00ff) | ((data as u16 & 0x3f) << 8);
+        } else {
+            self.t = (self.t & 0xff00) | data as u16;
+            self.v = self.t;
+        }
+        self.w = !self.w;
     }
 
-    pub fn reset_latch(&mut self) {
-        self.hi_ptr = true;
+    #[inline]
+    pub fn fine_y(&self) -> u16 {
+        (self.v >> 12) & 0b111
     }
 
-    pub fn get(&self) -> u16 {
-        ((self.value as u16) << 8) | (self.value as u16)
+    // coarse x + 1, wrapping into the horizontally adjacent nametable
+    pub fn increment_x(&mut self) {
+        if self.v & 0x001f == 31 {
+            self.v &= !0x001f;
+            self.v ^= 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    // fine y + 1, carrying into coarse y and the vertically adjacent nametable.
+    // coarse y 29 wraps to the next nametable, 31 wraps without switching.
+    pub fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let mut y = (self.v & 0x03e0) >> 5;
+        if y == 29 {
+            y = 0;
+            self.v ^= 0x0800;
+        } else if y == 31 {
+            y = 0;
+        } else {
+            y += 1;
+        }
+        self.v = (self.v & !0x03e0) | (y << 5);
+    }
+
+    pub fn copy_x(&mut self) {
+        self.v = (self.v & !0x041f) | (self.t & 0x041f);
+    }
+
+    pub fn copy_y(&mut self) {
+        self.v = (self.v & !0x7be0) | (self.t & 0x7be0);
+    }
+}
+
+// background tile fetch latches and the 16 bit shift registers they feed
+struct BackgroundPipeline {
+    next_tile: u8,
+    next_attr: u8,
+    next_lo: u8,
+    next_hi: u8,
+    pattern_lo: u16,
+    pattern_hi: u16,
+    attr_lo: u16,
+    attr_hi: u16,
+}
+
+impl BackgroundPipeline {
+    fn new() -> Self {
+        BackgroundPipeline {
+            next_tile: 0,
+            next_attr: 0,
+            next_lo: 0,
+            next_hi: 0,
+            pattern_lo: 0,
+            pattern_hi: 0,
+            attr_lo: 0,
+            attr_hi: 0,
+        }
+    }
+
+    fn reload(&mut self) {
+        self.pattern_lo = (self.pattern_lo & 0xff00) | self.next_lo as u16;
+        self.pattern_hi = (self.pattern_hi & 0xff00) | self.next_hi as u16;
+        self.attr_lo = (self.attr_lo & 0xff00) | if self.next_attr & 0b01 != 0 { 0xff } else { 0 };
+        self.attr_hi = (self.attr_hi & 0xff00) | if self.next_attr & 0b10 != 0 { 0xff } else { 0 };
+    }
+
+    fn shift(&mut self) {
+        self.pattern_lo <<= 1;
+        self.pattern_hi <<= 1;
+        self.attr_lo <<= 1;
+        self.attr_hi <<= 1;
+    }
+
+    // (2 bit colour, palette) under the fine x scroll
+    fn pixel(&self, fine_x: u8) -> (u8, u8) {
+        let mux = 0x8000 >> fine_x;
+        let value = ((self.pattern_hi & mux != 0) as u8) << 1 | (self.pattern_lo & mux != 0) as u8;
+        let palette = ((self.attr_hi & mux != 0) as u8) << 1 | (self.attr_lo & mux != 0) as u8;
+        (value, palette)
+    }
+}
+
+// up to 8 sprites found by evaluation for the line being drawn
+struct SpriteLine {
+    count: usize,
+    index: [u8; 8],
+    row: [u8; 8],
+    attr: [u8; 8],
+    x: [u8; 8],
+    lo: [u8; 8],
+    hi: [u8; 8],
+}
+
+impl SpriteLine {
+    fn new() -> Self {
+        SpriteLine {
+            count: 0,
+            index: [0; 8],
+            row: [0; 8],
+            attr: [0; 8],
+            x: [0; 8],
+            lo: [0; 8],
+            hi: [0; 8],
+        }
+    }
+
+    // first opaque sprite pixel at x as (2 bit colour, attributes, oam index).
+    // patterns are stored already flipped horizontally.
+    fn pixel(&self, x: usize) -> Option<(u8, u8, u8)> {
+        for i in 0..self.count {
+            let col = x.wrapping_sub(self.x[i] as usize);
+            if col >= 8 {
+                continue;
+            }
+            let bit = 7 - col;
+            let value = ((self.hi[i] >> bit) & 1) << 1 | ((self.lo[i] >> bit) & 1);
+            if value != 0 {
+                return Some((value, self.attr[i], self.index[i]));
+            }
+        }
+        None
     }
 }
 
@@ -120,6 +676,12 @@ bitflags! {
     }
 }
 
+impl Default for ControlRegister {
+    fn default() -> Self {
+        ControlRegister::new()
+    }
+}
+
 impl ControlRegister {
     pub fn new() -> Self {
         ControlRegister::from_bits_truncate(0b00000000)
@@ -133,7 +695,121 @@ impl ControlRegister {
         }
     }
 
+    pub fn sprt_pattern_addr(&self) -> u16 {
+        if !self.contains(ControlRegister::SPRITE_PATTERN_ADDR) {
+            0
+        } else {
+            0x1000
+        }
+    }
+
+    pub fn bknd_pattern_addr(&self) -> u16 {
+        if !self.contains(ControlRegister::BACKROUND_PATTERN_ADDR) {
+            0
+        } else {
+            0x1000
+        }
+    }
+
+    pub fn sprite_size(&self) -> u8 {
+        if !self.contains(ControlRegister::SPRITE_SIZE) {
+            8
+        } else {
+            16
+        }
+    }
+
     pub fn update(&mut self, data: u8) {
-        self.insert(ControlRegister::from_bits_truncate(data));
+        *self = ControlRegister::from_bits_truncate(data);
+    }
+}
+
+bitflags! {
+    pub struct StatusRegister: u8 {
+       const SPRITE_OVERFLOW         = 0b00100000;
+       const SPRITE_ZERO_HIT         = 0b01000000;
+       const VBLANK_STARTED          = 0b10000000;
+    }
+}
+
+impl Default for StatusRegister {
+    fn default() -> Self {
+        StatusRegister::new()
+    }
+}
+
+impl StatusRegister {
+    pub fn new() -> Self {
+        StatusRegister::from_bits_truncate(0b00000000)
+    }
+}
+
+bitflags! {
+    pub struct MaskRegister: u8 {
+       const GREYSCALE               = 0b00000001;
+       const LEFTMOST_8PXL_BACKGROUND = 0b00000010;
+       const LEFTMOST_8PXL_SPRITE    = 0b00000100;
+       const SHOW_BACKGROUND         = 0b00001000;
+       const SHOW_SPRITES            = 0b00010000;
+       const EMPHASISE_RED           = 0b00100000;
+       const EMPHASISE_GREEN         = 0b01000000;
+       const EMPHASISE_BLUE          = 0b10000000;
+    }
+}
+
+impl Default for MaskRegister {
+    fn default() -> Self {
+        MaskRegister::new()
+    }
+}
+
+impl MaskRegister {
+    pub fn new() -> Self {
+        MaskRegister::from_bits_truncate(0b00000000)
+    }
+
+    pub fn rendering_enabled(&self) -> bool {
+        self.intersects(MaskRegister::SHOW_BACKGROUND | MaskRegister::SHOW_SPRITES)
+    }
+
+    pub fn update(&mut self, data: u8) {
+        *self = MaskRegister::from_bits_truncate(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FourScreen;
+
+    impl Mapper for FourScreen {
+        fn cpu_read(&self, _addr: u16) -> Option<u8> {
+            None
+        }
+        fn cpu_write(&mut self, _addr: u16, _data: u8) {}
+        fn ppu_read(&mut self, _addr: u16) -> u8 {
+            0
+        }
+        fn ppu_write(&mut self, _addr: u16, _data: u8) {}
+        fn mirroring(&self) -> Mirroring {
+            Mirroring::FOUR_SCREEN
+        }
+    }
+
+    #[test]
+    fn four_screen_nametables_are_distinct() {
+        let mut ppu = Ppu::new();
+        let mut cart = FourScreen;
+        for nt in 0..4u16 {
+            ppu.mem_write(0x2000 + nt * 0x400, nt as u8 + 1, &mut cart);
+            ppu.mem_write(0x23ff + nt * 0x400, nt as u8 + 0x10, &mut cart);
+        }
+        for nt in 0..4u16 {
+            assert_eq!(ppu.mem_read(0x2000 + nt * 0x400, &mut cart), nt as u8 + 1);
+            assert_eq!(ppu.mem_read(0x23ff + nt * 0x400, &mut cart), nt as u8 + 0x10);
+            // $3000-$3eff mirrors $2000-$2eff
+            assert_eq!(ppu.mem_read(0x3000 + nt * 0x400, &mut cart), nt as u8 + 1);
+        }
     }
 }
@@ -0,0 +1,77 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// battery backed prg ram persisted to a file, usually <rom>.sav.
+// the file is rewritten only when the ram changed since the last flush.
+pub struct SaveFile {
+    path: PathBuf,
+    interval: Duration,
+    last_flush: Instant,
+    written: Vec<u8>,
+}
+
+impl SaveFile {
+    pub fn new(path: PathBuf, interval: Duration) -> Self {
+        SaveFile {
+            path: path,
+            interval: interval,
+            last_flush: Instant::now(),
+            written: vec![],
+        }
+    }
+
+    // copy the saved data into ram. a missing file leaves ram untouched.
+    // either way flushes start comparing against the ram as it is now, so a game
+    // that never touches its ram never creates a file.
+    pub fn load(&mut self, ram: &mut [u8]) -> io::Result<()> {
+        match fs::read(&self.path) {
+            Ok(data) => {
+                let len = data.len().min(ram.len());
+                ram[..len].copy_from_slice(&data[..len]);
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        self.written = ram.to_vec();
+        Ok(())
+    }
+
+    pub fn due(&self) -> bool {
+        self.last_flush.elapsed() >= self.interval
+    }
+
+    pub fn flush(&mut self, ram: &[u8]) -> io::Result<()> {
+        self.last_flush = Instant::now();
+        if self.written == ram {
+            return Ok(());
+        }
+        fs::write(&self.path, ram)?;
+        self.written = ram.to_vec();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flush_only_writes_changes() {
+        let path = std::env::temp_dir().join(format!("nesnes-save-test-{}.sav", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut ram = [0u8; 16];
+        let mut save = SaveFile::new(path.clone(), Duration::ZERO);
+        save.load(&mut ram).unwrap();
+        save.flush(&ram).unwrap();
+        assert!(!path.exists());
+
+        ram[3] = 0x42;
+        save.flush(&ram).unwrap();
+        let mut loaded = [0u8; 16];
+        SaveFile::new(path.clone(), Duration::ZERO).load(&mut loaded).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, ram);
+    }
+}
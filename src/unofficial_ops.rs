@@ -1,5 +1,17 @@
 // undocumented ops
 // https://www.nesdev.org/undocumented_opcodes.txt
+// names follow the document above. other common names:
+// aac=anc, aax=sax, asr=alr, atx=lxa, axa=sha, axs=sbx, dop/top=nop,
+// isc=isb, kil=jam, lar=las, sxa=shx, sya=shy, xaa=ane, xas=tas
+
+use log::debug;
+
+use crate::emu::Emu;
+use crate::ops::*;
+use crate::ops::addressing;
+
+// "magic" constant of the unstable xaa opcode. it depends on the chip and temperature.
+const XAA_MAGIC: u8 = 0xEE;
 
 #[inline]
 pub fn  aac(emu: &mut Emu, val: u8) {
@@ -11,32 +23,559 @@ pub fn  aac(emu: &mut Emu, val: u8) {
 pub fn  aax (emu: &mut Emu, addr: u16) {
     let r = emu.cpu.a & emu.cpu.x;
     emu.mem.store_u8(addr, r);
-    emu.cpu.nz_flags(r);
 }
 #[inline]
 pub fn arr (emu: &mut Emu, val: u8) {
-    let r = (emu.cpu.a & val).rotate_right(1);
+    let c = emu.cpu.sp.contains(crate::cpu::Flags::C) as u8;
+    let r = ((emu.cpu.a & val) >> 1) | (c << 7);
     let (bit_5, bit_6) = {
         (r & 0b0010_0000 != 0, r & 0b0100_0000 != 0)
     };
-    match (bit_5, bit_6) {
-        (true, true) => { emu.cpu.carry_flag(true); emu.cpu.overflow_flag(false); },
-        (true, false) => { emu.cpu.carry_flag(false); emu.cpu.overflow_flag(true); },
-        (false, true) => { emu.cpu.carry_flag(false); emu.cpu.overflow_flag(true); },
-        (false, false) => { emu.cpu.carry_flag(false); emu.cpu.overflow_flag(false); }
-    }
+    emu.cpu.carry_flag(bit_6);
+    emu.cpu.overflow_flag(bit_5 ^ bit_6);
+    emu.cpu.a = r;
     emu.cpu.nz_flags(r);
 }
 #[inline]
 pub fn asr (emu: &mut Emu, val: u8) {
-    let r = (val & emu.cpu.a) >> 1;
-    emu.cpu.carry_flag(emu.cpu.a & 0x01 != 0);
-    emu.cpu.nz_flags(r);
+    let r = val & emu.cpu.a;
+    emu.cpu.carry_flag(r & 0x01 != 0);
+    emu.cpu.a = r >> 1;
+    emu.cpu.nz_flags(emu.cpu.a);
 }
 #[inline]
 pub fn atx (emu: &mut Emu, val: u8) {
-    emu.cpu.x = emu.cpu.a & val;
+    emu.cpu.a = emu.cpu.a & val;
+    emu.cpu.x = emu.cpu.a;
     emu.cpu.nz_flags(emu.cpu.x);
 }
+#[inline]
+pub fn axs (emu: &mut Emu, val: u8) {
+    let (r, v) = (emu.cpu.a & emu.cpu.x).overflowing_sub(val);
+    emu.cpu.x = r;
+    emu.cpu.carry_flag(!v);
+    emu.cpu.nz_flags(r);
+}
+#[inline]
+pub fn dcp (emu: &mut Emu, addr: u16) {
+    let val = dec(emu, addr);
+    cmp(emu, val);
+}
+#[inline]
+pub fn isc (emu: &mut Emu, addr: u16) {
+    let val = inc(emu, addr);
+    sbc(emu, val);
+}
+#[inline]
+pub fn lar (emu: &mut Emu, val: u8) {
+    let r = val & emu.cpu.s;
+    emu.cpu.a = r;
+    emu.cpu.x = r;
+    emu.cpu.s = r;
+    emu.cpu.nz_flags(r);
+}
+#[inline]
+pub fn lax (emu: &mut Emu, val: u8) {
+    emu.cpu.a = val;
+    emu.cpu.x = val;
+    emu.cpu.nz_flags(val);
+}
+#[inline]
+pub fn rla (emu: &mut Emu, addr: u16) {
+    let val = rol(emu, addr);
+    and(emu, val);
+}
+#[inline]
+pub fn rra (emu: &mut Emu, addr: u16) {
+    let val = ror(emu, addr);
+    adc(emu, val);
+}
+#[inline]
+pub fn slo (emu: &mut Emu, addr: u16) {
+    let val = asl(emu, addr);
+    ora(emu, val);
+}
+#[inline]
+pub fn sre (emu: &mut Emu, addr: u16) {
+    let val = lsr(emu, addr);
+    eor(emu, val);
+}
+#[inline]
+pub fn xaa (emu: &mut Emu, val: u8) {
+    emu.cpu.a = (emu.cpu.a | XAA_MAGIC) & emu.cpu.x & val;
+    emu.cpu.nz_flags(emu.cpu.a);
+}
+// axa, sxa, sya and xas store `val & (high byte of base + 1)`.
+// when the index crosses a page the stored value also replaces the high byte of the address.
+#[inline]
+fn sh (emu: &mut Emu, base: u16, index: u8, val: u8) {
+    let addr = base.wrapping_add(index as u16);
+    let r = val & ((base >> 8) as u8).wrapping_add(1);
+    let addr = if (base ^ addr) & 0xFF00 != 0 {
+        (addr & 0x00FF) | ((r as u16) << 8)
+    } else {
+        addr
+    };
+    emu.mem.store_u8(addr, r);
+}
+#[inline]
+fn absolute_base (emu: &mut Emu) -> u16 {
+    emu.mem.load_u16(emu.cpu.pc+1)
+}
+#[inline]
+fn indirect_base (emu: &mut Emu) -> u16 {
+    let addr = emu.mem.load_u8(emu.cpu.pc+1);
+    emu.mem.load_u8(addr as u16) as u16 | ((emu.mem.load_u8(addr.wrapping_add(1) as u16) as u16) << 8)
+}
 
-// todo : write more
+// ops with addressing mode
+pub fn kil (emu: &mut Emu) {
+    debug!("cpu jammed at {:04X}", emu.cpu.pc);
+    emu.cpu.jammed = true;
+}
+pub fn aac_imm(emu: &mut Emu) {
+    let addr = addressing::immediate(emu);
+    let val = emu.mem.load_u8(addr);
+    aac(emu, val);
+    emu.cpu.pc += 2;
+}
+pub fn aax_zpg(emu: &mut Emu) {
+    let addr = addressing::zeropage(emu);
+    aax(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn aax_zpy(emu: &mut Emu) {
+    let addr = addressing::zeropage_y(emu);
+    aax(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn aax_abs(emu: &mut Emu) {
+    let addr = addressing::absolute(emu);
+    aax(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn aax_inx(emu: &mut Emu) {
+    let addr = addressing::indirect_x(emu);
+    aax(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn arr_imm(emu: &mut Emu) {
+    let addr = addressing::immediate(emu);
+    let val = emu.mem.load_u8(addr);
+    arr(emu, val);
+    emu.cpu.pc += 2;
+}
+pub fn asr_imm(emu: &mut Emu) {
+    let addr = addressing::immediate(emu);
+    let val = emu.mem.load_u8(addr);
+    asr(emu, val);
+    emu.cpu.pc += 2;
+}
+pub fn atx_imm(emu: &mut Emu) {
+    let addr = addressing::immediate(emu);
+    let val = emu.mem.load_u8(addr);
+    atx(emu, val);
+    emu.cpu.pc += 2;
+}
+pub fn axa_aby(emu: &mut Emu) {
+    let base = absolute_base(emu);
+    sh(emu, base, emu.cpu.y, emu.cpu.a & emu.cpu.x);
+    emu.cpu.pc += 3;
+}
+pub fn axa_iny(emu: &mut Emu) {
+    let base = indirect_base(emu);
+    sh(emu, base, emu.cpu.y, emu.cpu.a & emu.cpu.x);
+    emu.cpu.pc += 2;
+}
+pub fn axs_imm(emu: &mut Emu) {
+    let addr = addressing::immediate(emu);
+    let val = emu.mem.load_u8(addr);
+    axs(emu, val);
+    emu.cpu.pc += 2;
+}
+pub fn dcp_zpg(emu: &mut Emu) {
+    let addr = addressing::zeropage(emu);
+    dcp(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn dcp_zpx(emu: &mut Emu) {
+    let addr = addressing::zeropage_x(emu);
+    dcp(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn dcp_abs(emu: &mut Emu) {
+    let addr = addressing::absolute(emu);
+    dcp(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn dcp_abx(emu: &mut Emu) {
+    let addr = addressing::absolute_x(emu);
+    dcp(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn dcp_aby(emu: &mut Emu) {
+    let addr = addressing::absolute_y(emu);
+    dcp(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn dcp_inx(emu: &mut Emu) {
+    let addr = addressing::indirect_x(emu);
+    dcp(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn dcp_iny(emu: &mut Emu) {
+    let addr = addressing::indirect_y(emu);
+    dcp(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn dop_imm(emu: &mut Emu) {
+    emu.cpu.pc += 2;
+}
+pub fn dop_zpg(emu: &mut Emu) {
+    let addr = addressing::zeropage(emu);
+    emu.mem.load_u8(addr);
+    emu.cpu.pc += 2;
+}
+pub fn dop_zpx(emu: &mut Emu) {
+    let addr = addressing::zeropage_x(emu);
+    emu.mem.load_u8(addr);
+    emu.cpu.pc += 2;
+}
+pub fn isc_zpg(emu: &mut Emu) {
+    let addr = addressing::zeropage(emu);
+    isc(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn isc_zpx(emu: &mut Emu) {
+    let addr = addressing::zeropage_x(emu);
+    isc(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn isc_abs(emu: &mut Emu) {
+    let addr = addressing::absolute(emu);
+    isc(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn isc_abx(emu: &mut Emu) {
+    let addr = addressing::absolute_x(emu);
+    isc(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn isc_aby(emu: &mut Emu) {
+    let addr = addressing::absolute_y(emu);
+    isc(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn isc_inx(emu: &mut Emu) {
+    let addr = addressing::indirect_x(emu);
+    isc(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn isc_iny(emu: &mut Emu) {
+    let addr = addressing::indirect_y(emu);
+    isc(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn lar_aby(emu: &mut Emu) {
+    let addr = addressing::absolute_y(emu);
+    let val = emu.mem.load_u8(addr);
+    lar(emu, val);
+    emu.cpu.pc += 3;
+}
+pub fn lax_zpg(emu: &mut Emu) {
+    let addr = addressing::zeropage(emu);
+    let val = emu.mem.load_u8(addr);
+    lax(emu, val);
+    emu.cpu.pc += 2;
+}
+pub fn lax_zpy(emu: &mut Emu) {
+    let addr = addressing::zeropage_y(emu);
+    let val = emu.mem.load_u8(addr);
+    lax(emu, val);
+    emu.cpu.pc += 2;
+}
+pub fn lax_abs(emu: &mut Emu) {
+    let addr = addressing::absolute(emu);
+    let val = emu.mem.load_u8(addr);
+    lax(emu, val);
+    emu.cpu.pc += 3;
+}
+pub fn lax_aby(emu: &mut Emu) {
+    let addr = addressing::absolute_y(emu);
+    let val = emu.mem.load_u8(addr);
+    lax(emu, val);
+    emu.cpu.pc += 3;
+}
+pub fn lax_inx(emu: &mut Emu) {
+    let addr = addressing::indirect_x(emu);
+    let val = emu.mem.load_u8(addr);
+    lax(emu, val);
+    emu.cpu.pc += 2;
+}
+pub fn lax_iny(emu: &mut Emu) {
+    let addr = addressing::indirect_y(emu);
+    let val = emu.mem.load_u8(addr);
+    lax(emu, val);
+    emu.cpu.pc += 2;
+}
+pub fn rla_zpg(emu: &mut Emu) {
+    let addr = addressing::zeropage(emu);
+    rla(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn rla_zpx(emu: &mut Emu) {
+    let addr = addressing::zeropage_x(emu);
+    rla(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn rla_abs(emu: &mut Emu) {
+    let addr = addressing::absolute(emu);
+    rla(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn rla_abx(emu: &mut Emu) {
+    let addr = addressing::absolute_x(emu);
+    rla(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn rla_aby(emu: &mut Emu) {
+    let addr = addressing::absolute_y(emu);
+    rla(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn rla_inx(emu: &mut Emu) {
+    let addr = addressing::indirect_x(emu);
+    rla(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn rla_iny(emu: &mut Emu) {
+    let addr = addressing::indirect_y(emu);
+    rla(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn rra_zpg(emu: &mut Emu) {
+    let addr = addressing::zeropage(emu);
+    rra(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn rra_zpx(emu: &mut Emu) {
+    let addr = addressing::zeropage_x(emu);
+    rra(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn rra_abs(emu: &mut Emu) {
+    let addr = addressing::absolute(emu);
+    rra(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn rra_abx(emu: &mut Emu) {
+    let addr = addressing::absolute_x(emu);
+    rra(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn rra_aby(emu: &mut Emu) {
+    let addr = addressing::absolute_y(emu);
+    rra(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn rra_inx(emu: &mut Emu) {
+    let addr = addressing::indirect_x(emu);
+    rra(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn rra_iny(emu: &mut Emu) {
+    let addr = addressing::indirect_y(emu);
+    rra(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn slo_zpg(emu: &mut Emu) {
+    let addr = addressing::zeropage(emu);
+    slo(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn slo_zpx(emu: &mut Emu) {
+    let addr = addressing::zeropage_x(emu);
+    slo(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn slo_abs(emu: &mut Emu) {
+    let addr = addressing::absolute(emu);
+    slo(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn slo_abx(emu: &mut Emu) {
+    let addr = addressing::absolute_x(emu);
+    slo(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn slo_aby(emu: &mut Emu) {
+    let addr = addressing::absolute_y(emu);
+    slo(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn slo_inx(emu: &mut Emu) {
+    let addr = addressing::indirect_x(emu);
+    slo(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn slo_iny(emu: &mut Emu) {
+    let addr = addressing::indirect_y(emu);
+    slo(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn sre_zpg(emu: &mut Emu) {
+    let addr = addressing::zeropage(emu);
+    sre(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn sre_zpx(emu: &mut Emu) {
+    let addr = addressing::zeropage_x(emu);
+    sre(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn sre_abs(emu: &mut Emu) {
+    let addr = addressing::absolute(emu);
+    sre(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn sre_abx(emu: &mut Emu) {
+    let addr = addressing::absolute_x(emu);
+    sre(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn sre_aby(emu: &mut Emu) {
+    let addr = addressing::absolute_y(emu);
+    sre(emu, addr);
+    emu.cpu.pc += 3;
+}
+pub fn sre_inx(emu: &mut Emu) {
+    let addr = addressing::indirect_x(emu);
+    sre(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn sre_iny(emu: &mut Emu) {
+    let addr = addressing::indirect_y(emu);
+    sre(emu, addr);
+    emu.cpu.pc += 2;
+}
+pub fn sxa_aby(emu: &mut Emu) {
+    let base = absolute_base(emu);
+    sh(emu, base, emu.cpu.y, emu.cpu.x);
+    emu.cpu.pc += 3;
+}
+pub fn sya_abx(emu: &mut Emu) {
+    let base = absolute_base(emu);
+    sh(emu, base, emu.cpu.x, emu.cpu.y);
+    emu.cpu.pc += 3;
+}
+pub fn top_abs(emu: &mut Emu) {
+    let addr = addressing::absolute(emu);
+    emu.mem.load_u8(addr);
+    emu.cpu.pc += 3;
+}
+pub fn top_abx(emu: &mut Emu) {
+    let addr = addressing::absolute_x(emu);
+    emu.mem.load_u8(addr);
+    emu.cpu.pc += 3;
+}
+pub fn xaa_imm(emu: &mut Emu) {
+    let addr = addressing::immediate(emu);
+    let val = emu.mem.load_u8(addr);
+    xaa(emu, val);
+    emu.cpu.pc += 2;
+}
+pub fn xas_aby(emu: &mut Emu) {
+    emu.cpu.s = emu.cpu.a & emu.cpu.x;
+    let base = absolute_base(emu);
+    sh(emu, base, emu.cpu.y, emu.cpu.s);
+    emu.cpu.pc += 3;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::Flags;
+    use crate::emu::Emu;
+    use crate::fixture::run;
+
+    #[test]
+    fn lax_sax() {
+        let mut emu = Emu::default();
+        emu.mem.copy_from_slice(0x10, &[0x80]);
+        // lax $10
+        assert_eq!(run(&mut emu, &[0xa7, 0x10], 1), 3);
+        assert_eq!((emu.cpu.a, emu.cpu.x), (0x80, 0x80));
+        assert!(emu.cpu.sp.contains(Flags::N) && !emu.cpu.sp.contains(Flags::Z));
+
+        // lda #$f0, ldx #$3c, sax $20
+        let mut emu = Emu::default();
+        assert_eq!(run(&mut emu, &[0xa9, 0xf0, 0xa2, 0x3c, 0x87, 0x20], 3), 7);
+        assert_eq!(emu.mem.peek_u8(0x20), 0x30);
+        // flags are left as ldx set them
+        assert!(!emu.cpu.sp.intersects(Flags::N | Flags::Z));
+    }
+
+    #[test]
+    fn read_modify_write_combos() {
+        let mut emu = Emu::default();
+        emu.mem.copy_from_slice(0x30, &[0x05, 0x0f, 0x81]);
+        // lda #$04, dcp $30: $30 = 4, compare equal
+        assert_eq!(run(&mut emu, &[0xa9, 0x04, 0xc7, 0x30], 2), 7);
+        assert_eq!(emu.mem.peek_u8(0x30), 0x04);
+        assert!(emu.cpu.sp.contains(Flags::Z | Flags::C));
+
+        // lda #$20, sec, isc $31: $31 = $10, a = $20 - $10
+        let mut emu2 = Emu::default();
+        emu2.mem.copy_from_slice(0x31, &[0x0f]);
+        assert_eq!(run(&mut emu2, &[0xa9, 0x20, 0x38, 0xe7, 0x31], 3), 9);
+        assert_eq!((emu2.mem.peek_u8(0x31), emu2.cpu.a), (0x10, 0x10));
+        assert!(emu2.cpu.sp.contains(Flags::C));
+
+        // lda #$02, slo $32: $32 = $02 with carry out, a = $02 | $02
+        let mut emu3 = Emu::default();
+        emu3.mem.copy_from_slice(0x32, &[0x81]);
+        assert_eq!(run(&mut emu3, &[0xa9, 0x02, 0x07, 0x32], 2), 7);
+        assert_eq!((emu3.mem.peek_u8(0x32), emu3.cpu.a), (0x02, 0x02));
+        assert!(emu3.cpu.sp.contains(Flags::C));
+    }
+
+    #[test]
+    fn sbc_eb_and_xaa() {
+        // sec, lda #$05, sbc #$01 (the $eb copy)
+        let mut emu = Emu::default();
+        assert_eq!(run(&mut emu, &[0x38, 0xa9, 0x05, 0xeb, 0x01], 3), 6);
+        assert_eq!(emu.cpu.a, 0x04);
+        assert!(emu.cpu.sp.contains(Flags::C));
+
+        // lda #$11, ldx #$ff, xaa #$0f: (a | magic) & x & imm
+        let mut emu = Emu::default();
+        run(&mut emu, &[0xa9, 0x11, 0xa2, 0xff, 0x8b, 0x0f], 3);
+        assert_eq!(emu.cpu.a, 0x0f);
+    }
+
+    #[test]
+    fn shx_page_cross_replaces_high_byte() {
+        // ldx #$05, ldy #$01, shx $0200,y: stores x & ($02 + 1)
+        let mut emu = Emu::default();
+        assert_eq!(run(&mut emu, &[0xa2, 0x05, 0xa0, 0x01, 0x9e, 0x00, 0x02], 3), 9);
+        assert_eq!(emu.mem.peek_u8(0x0201), 0x01);
+
+        // shx $02ff,y crosses into $0300. the stored value becomes the high byte: $0100.
+        let mut emu = Emu::default();
+        run(&mut emu, &[0xa2, 0x05, 0xa0, 0x01, 0x9e, 0xff, 0x02], 3);
+        assert_eq!(emu.mem.peek_u8(0x0300), 0x00);
+        assert_eq!(emu.mem.peek_u8(0x0100), 0x01);
+    }
+
+    #[test]
+    fn nops_and_kil() {
+        let mut emu = Emu::default();
+        // dop $10, top $1234
+        assert_eq!(run(&mut emu, &[0x04, 0x10, 0x0c, 0x34, 0x12], 2), 7);
+        assert_eq!(emu.cpu.pc, 0x0605);
+
+        let mut emu = Emu::default();
+        run(&mut emu, &[0x02, 0xea], 3);
+        assert!(emu.cpu.jammed);
+        assert_eq!(emu.cpu.pc, 0x0600);
+    }
+}